@@ -0,0 +1,122 @@
+// 启用embed特性时把静态资源目录编译进可执行文件
+#[allow(dead_code)]
+#[path = "src/constant.rs"]
+mod constant;
+#[allow(dead_code)]
+#[path = "src/mime.rs"]
+mod mime;
+
+use mime::MimeTypes;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=EMBED_DIR");
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let embed_dir = env::var("EMBED_DIR").unwrap_or_else(|_| String::from("static"));
+    let embed_dir = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join(embed_dir);
+    println!("cargo:rustc-env=EMBED_DIR={}", embed_dir.display());
+    if env::var_os("CARGO_FEATURE_EMBED").is_none() {
+        return;
+    }
+    println!("cargo:rerun-if-changed={}", embed_dir.display());
+    let precompress = env::var_os("CARGO_FEATURE_EMBED_PRECOMPRESSED").is_some();
+
+    let mut files = Vec::new();
+    collect(&embed_dir, &embed_dir, &mut files);
+    files.sort();
+
+    let mime_types = MimeTypes::new();
+    let mut code = String::from("pub static ASSETS: &[Asset] = &[\n");
+    for (i, (url, path)) in files.iter().enumerate() {
+        println!("cargo:rerun-if-changed={}", path.display());
+        let contents = fs::read(path).unwrap();
+        let modified = fs::metadata(path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_secs());
+        let etag = format!("\"{:x}-{:x}\"", contents.len(), fnv1a(&contents));
+        let mime = mime_types.guess(path, &contents);
+        let (gzip, br) = if precompress {
+            (
+                compressed(&out_dir, i, "gz", &contents, gzip(&contents)),
+                compressed(&out_dir, i, "br", &contents, brotli(&contents)),
+            )
+        } else {
+            (String::from("None"), String::from("None"))
+        };
+        code.push_str(&format!(
+            "    Asset {{ path: {:?}, contents: include_bytes!({:?}), mime: {:?}, etag: {:?}, modified: {}, gzip: {}, br: {} }},\n",
+            url,
+            path.display().to_string(),
+            mime,
+            etag,
+            modified,
+            gzip,
+            br,
+        ));
+    }
+    code.push_str("];\n");
+    fs::write(out_dir.join("embedded.rs"), code).unwrap();
+}
+
+// 递归收集文件，跳过.开头的文件和目录
+fn collect(root: &Path, dir: &Path, files: &mut Vec<(String, PathBuf)>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        if name.starts_with('.') {
+            continue;
+        }
+        if path.is_dir() {
+            collect(root, &path, files);
+        } else if path.is_file() {
+            let relative = path.strip_prefix(root).unwrap();
+            let url: Vec<_> = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().to_string())
+                .collect();
+            files.push((format!("/{}", url.join("/")), path));
+        }
+    }
+}
+
+// 压缩后更小时写入OUT_DIR并引用，否则不保存
+fn compressed(
+    out_dir: &Path,
+    index: usize,
+    extension: &str,
+    original: &[u8],
+    data: Vec<u8>,
+) -> String {
+    if data.len() >= original.len() {
+        return String::from("None");
+    }
+    let path = out_dir.join(format!("asset_{}.{}", index, extension));
+    fs::write(&path, data).unwrap();
+    format!("Some(include_bytes!({:?}))", path.display().to_string())
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn brotli(data: &[u8]) -> Vec<u8> {
+    let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, 11, 22);
+    encoder.write_all(data).unwrap();
+    encoder.into_inner()
+}
+
+// 内容的FNV-1a哈希，用于生成ETag
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
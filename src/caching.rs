@@ -0,0 +1,315 @@
+use crate::request::{HttpMethod, HttpRequest};
+use crate::response::HttpStatus;
+use crate::utils::{http_date, parse_http_date};
+use std::fs::Metadata;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// ETag生成方式
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EtagMode {
+    Strong,
+    Weak,
+    Off,
+}
+
+// 资源的验证器
+#[derive(Clone, Debug)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<SystemTime>,
+}
+
+impl Validators {
+    // 根据文件大小和修改时间生成
+    pub fn from_metadata(metadata: &Metadata, mode: EtagMode) -> Self {
+        let last_modified = metadata.modified().ok();
+        let nanos = last_modified
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_nanos());
+        let tag = format!("\"{:x}-{:x}\"", metadata.len(), nanos);
+        Self::from_tag(tag, last_modified, mode)
+    }
+
+    // tag为带引号的强ETag，按mode转换
+    pub fn from_tag(tag: String, last_modified: Option<SystemTime>, mode: EtagMode) -> Self {
+        let etag = match mode {
+            EtagMode::Strong => Some(tag),
+            EtagMode::Weak => Some(format!("W/{}", tag)),
+            EtagMode::Off => None,
+        };
+        Self {
+            etag,
+            last_modified,
+        }
+    }
+
+    // Last-Modified响应头
+    pub fn last_modified_header(&self) -> Option<String> {
+        self.last_modified.map(http_date)
+    }
+
+    // 按RFC 9110的顺序检查条件请求，需要返回304或412时返回对应状态码
+    pub fn evaluate(&self, req: &HttpRequest) -> Option<HttpStatus> {
+        let headers = req.headers();
+        if let Some(&if_match) = headers.get("if-match") {
+            if !self.etag_matches(if_match, true) {
+                return Some(HttpStatus::PreconditionFailed);
+            }
+        } else if let Some(&since) = headers.get("if-unmodified-since") {
+            if let (Some(modified), Some(since)) = (self.last_modified, parse_http_date(since)) {
+                if truncate(modified) > since {
+                    return Some(HttpStatus::PreconditionFailed);
+                }
+            }
+        }
+
+        let safe = matches!(req.method(), HttpMethod::Get | HttpMethod::Head);
+        if let Some(&if_none_match) = headers.get("if-none-match") {
+            if self.etag_matches(if_none_match, false) {
+                return Some(if safe {
+                    HttpStatus::NotModified
+                } else {
+                    HttpStatus::PreconditionFailed
+                });
+            }
+        } else if let Some(&since) = headers.get("if-modified-since") {
+            if let (true, Some(modified), Some(since)) =
+                (safe, self.last_modified, parse_http_date(since))
+            {
+                if truncate(modified) <= since {
+                    return Some(HttpStatus::NotModified);
+                }
+            }
+        }
+        None
+    }
+
+    // 强比较要求两边都不是弱ETag，弱比较只比较值
+    fn etag_matches(&self, header: &str, strong: bool) -> bool {
+        let etag = match &self.etag {
+            Some(etag) => etag,
+            None => return header.trim() == "*",
+        };
+        if strong && etag.starts_with("W/") {
+            return false;
+        }
+        let value = etag.trim_start_matches("W/");
+        header.split(',').map(str::trim).any(|tag| {
+            tag == "*"
+                || (!(strong && tag.starts_with("W/")) && tag.trim_start_matches("W/") == value)
+        })
+    }
+}
+
+// HTTP日期只精确到秒
+fn truncate(time: SystemTime) -> SystemTime {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    UNIX_EPOCH + std::time::Duration::from_secs(secs)
+}
+
+// 按路径设置Cache-Control，先添加的规则优先
+#[derive(Clone, Debug, Default)]
+pub struct CachePolicy {
+    rules: Vec<(String, String)>,
+}
+
+#[allow(dead_code)]
+impl CachePolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // pattern支持*通配，如 *.css、/assets/*
+    pub fn insert(&mut self, pattern: &str, cache_control: &str) {
+        self.rules
+            .push((pattern.to_string(), cache_control.to_string()));
+    }
+
+    pub fn lookup(&self, path: &str) -> Option<&str> {
+        self.rules
+            .iter()
+            .find(|(pattern, _)| glob_match(pattern.as_bytes(), path.as_bytes()))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+// 简单的通配符匹配，*匹配任意字符。
+// 遇到不匹配时回到上一个*多吞一个字符，最坏是O(m*n)，不会像递归那样指数增长
+pub fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // 上一个*的位置和它开始匹配的文本位置
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            star = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, t));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODIFIED: &str = "Sun, 06 Nov 1994 08:49:37 GMT";
+    const EARLIER: &str = "Sat, 05 Nov 1994 08:49:37 GMT";
+
+    fn validators() -> Validators {
+        Validators::from_tag(
+            String::from("\"v1\""),
+            parse_http_date(MODIFIED),
+            EtagMode::Strong,
+        )
+    }
+
+    fn evaluate(
+        validators: &Validators,
+        method: &str,
+        headers: &[(&str, &str)],
+    ) -> Option<HttpStatus> {
+        let mut head = format!("{} / HTTP/1.1\r\nHost: localhost\r\n", method);
+        for (name, value) in headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        validators.evaluate(&HttpRequest::from(&head, Vec::new(), "127.0.0.1").unwrap())
+    }
+
+    #[test]
+    fn glob() {
+        assert!(glob_match(b"*.css", b"/a/b.css"));
+        assert!(glob_match(b"/assets/*", b"/assets/"));
+        assert!(glob_match(b"/assets/*", b"/assets/a/b.js"));
+        assert!(glob_match(b"*", b""));
+        assert!(glob_match(b"a**b", b"ab"));
+        assert!(glob_match(b"*a*b", b"xaybzb"));
+        assert!(!glob_match(b"*.css", b"/a.css.map"));
+        assert!(!glob_match(b"/assets/*", b"/asset"));
+        assert!(!glob_match(b"abc", b"ab"));
+        assert!(!glob_match(b"", b"a"));
+        // 多个*时不会指数回溯
+        let text = [b'a'; 200];
+        assert!(!glob_match(b"*a*a*a*a*a*a*a*a*a*a*a*a*b", &text));
+    }
+
+    #[test]
+    fn cache_policy_first_rule_wins() {
+        let mut policy = CachePolicy::new();
+        policy.insert("/assets/*.css", "max-age=60");
+        policy.insert("*.css", "no-cache");
+        assert_eq!(policy.lookup("/assets/a.css"), Some("max-age=60"));
+        assert_eq!(policy.lookup("/b.css"), Some("no-cache"));
+        assert_eq!(policy.lookup("/b.js"), None);
+    }
+
+    #[test]
+    fn etag_mode() {
+        let weak = Validators::from_tag(String::from("\"v1\""), None, EtagMode::Weak);
+        assert_eq!(weak.etag.as_deref(), Some("W/\"v1\""));
+        let off = Validators::from_tag(String::from("\"v1\""), None, EtagMode::Off);
+        assert_eq!(off.etag, None);
+    }
+
+    #[test]
+    fn if_match_before_if_unmodified_since() {
+        let v = validators();
+        assert_eq!(evaluate(&v, "PUT", &[("If-Match", "\"v1\"")]), None);
+        assert_eq!(evaluate(&v, "PUT", &[("If-Match", "*")]), None);
+        assert_eq!(
+            evaluate(&v, "PUT", &[("If-Match", "\"v2\", \"v3\"")]),
+            Some(HttpStatus::PreconditionFailed)
+        );
+        // If-Match使用强比较
+        assert_eq!(
+            evaluate(&v, "PUT", &[("If-Match", "W/\"v1\"")]),
+            Some(HttpStatus::PreconditionFailed)
+        );
+        assert_eq!(
+            evaluate(&v, "PUT", &[("If-Unmodified-Since", EARLIER)]),
+            Some(HttpStatus::PreconditionFailed)
+        );
+        assert_eq!(
+            evaluate(&v, "PUT", &[("If-Unmodified-Since", MODIFIED)]),
+            None
+        );
+        // 有If-Match时忽略If-Unmodified-Since
+        assert_eq!(
+            evaluate(
+                &v,
+                "PUT",
+                &[("If-Match", "\"v1\""), ("If-Unmodified-Since", EARLIER)]
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn if_none_match_before_if_modified_since() {
+        let v = validators();
+        // If-None-Match使用弱比较
+        assert_eq!(
+            evaluate(&v, "GET", &[("If-None-Match", "W/\"v1\"")]),
+            Some(HttpStatus::NotModified)
+        );
+        assert_eq!(
+            evaluate(&v, "HEAD", &[("If-None-Match", "*")]),
+            Some(HttpStatus::NotModified)
+        );
+        assert_eq!(
+            evaluate(&v, "POST", &[("If-None-Match", "\"v1\"")]),
+            Some(HttpStatus::PreconditionFailed)
+        );
+        assert_eq!(
+            evaluate(&v, "GET", &[("If-Modified-Since", MODIFIED)]),
+            Some(HttpStatus::NotModified)
+        );
+        assert_eq!(evaluate(&v, "GET", &[("If-Modified-Since", EARLIER)]), None);
+        // 只有GET和HEAD使用If-Modified-Since
+        assert_eq!(
+            evaluate(&v, "POST", &[("If-Modified-Since", MODIFIED)]),
+            None
+        );
+        // 有If-None-Match时忽略If-Modified-Since
+        assert_eq!(
+            evaluate(
+                &v,
+                "GET",
+                &[("If-None-Match", "\"v2\""), ("If-Modified-Since", MODIFIED)]
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn precondition_failed_before_not_modified() {
+        let v = validators();
+        assert_eq!(
+            evaluate(
+                &v,
+                "GET",
+                &[("If-Match", "\"v2\""), ("If-None-Match", "\"v1\"")]
+            ),
+            Some(HttpStatus::PreconditionFailed)
+        );
+        assert_eq!(
+            evaluate(
+                &v,
+                "GET",
+                &[
+                    ("If-Unmodified-Since", EARLIER),
+                    ("If-Modified-Since", MODIFIED)
+                ]
+            ),
+            Some(HttpStatus::PreconditionFailed)
+        );
+    }
+}
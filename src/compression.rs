@@ -0,0 +1,475 @@
+use crate::middleware::{Middleware, Next};
+use crate::request::HttpRequest;
+use crate::response::{Body, HttpResponse, HttpStatus};
+use brotli::CompressorWriter;
+use flate2::write::{GzEncoder, ZlibEncoder};
+use std::io::{self, Write};
+use tokio::sync::mpsc;
+
+// 支持的编码，q值相同时靠前的优先
+const CODINGS: [&str; 3] = ["br", "gzip", "deflate"];
+
+// 超过该大小的完整响应体在阻塞线程中压缩，避免占用异步运行时
+const BLOCKING_SIZE: usize = 64 * 1024;
+
+// 已经压缩过的类型，再压缩没有收益
+const COMPRESSED_TYPES: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "image/avif",
+    "font/woff",
+    "font/woff2",
+    "application/zip",
+    "application/gzip",
+    "application/x-7z-compressed",
+    "application/vnd.rar",
+    "application/pdf",
+    "application/octet-stream",
+];
+
+// 解析Accept-Encoding，返回编码名称和q值
+pub fn parse_accept_encoding(header: &str) -> Vec<(String, f32)> {
+    header
+        .split(',')
+        .filter_map(|part| {
+            let mut params = part.split(';');
+            let coding = params.next()?.trim().to_lowercase();
+            if coding.is_empty() {
+                return None;
+            }
+            let q = params
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            Some((coding, q))
+        })
+        .collect()
+}
+
+// 编码的q值，明确列出的优先于*
+fn quality(codings: &[(String, f32)], coding: &str) -> f32 {
+    codings
+        .iter()
+        .find(|(c, _)| c == coding || (coding == "gzip" && c == "x-gzip"))
+        .or_else(|| codings.iter().find(|(c, _)| c == "*"))
+        .map_or(0.0, |(_, q)| *q)
+}
+
+// 客户端是否接受该编码
+pub fn accepts(header: Option<&str>, coding: &str) -> bool {
+    match header {
+        Some(header) => quality(&parse_accept_encoding(header), coding) > 0.0,
+        None => false,
+    }
+}
+
+// 选出q值最高的编码
+fn negotiate(header: &str) -> Option<&'static str> {
+    let codings = parse_accept_encoding(header);
+    let mut best: Option<(&'static str, f32)> = None;
+    for coding in CODINGS {
+        let q = quality(&codings, coding);
+        if q > 0.0 && best.is_none_or(|(_, b)| q > b) {
+            best = Some((coding, q));
+        }
+    }
+    best.map(|(coding, _)| coding)
+}
+
+// 响应压缩中间件，根据Accept-Encoding使用br、gzip或deflate
+#[derive(Clone, Debug)]
+pub struct Compression {
+    // 小于该大小的响应体不压缩，流式响应体不受限制
+    min_size: usize,
+    // gzip和deflate的压缩级别 0-9
+    gzip_level: u32,
+    // brotli的压缩级别 0-11
+    brotli_level: u32,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self {
+            min_size: 1024,
+            gzip_level: 6,
+            brotli_level: 4,
+        }
+    }
+}
+
+impl Compression {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    pub fn gzip_level(mut self, level: u32) -> Self {
+        self.gzip_level = level.min(9);
+        self
+    }
+
+    pub fn brotli_level(mut self, level: u32) -> Self {
+        self.brotli_level = level.min(11);
+        self
+    }
+
+    // 响应是否适合压缩，与客户端是否支持无关
+    fn compressible(&self, response: &HttpResponse) -> bool {
+        if matches!(
+            response.status(),
+            HttpStatus::PartialContent | HttpStatus::NotModified
+        ) || response.header("Content-Encoding").is_some()
+            || response.header("Content-Range").is_some()
+        {
+            return false;
+        }
+        self.compressible_type(response)
+            && match response.body() {
+                Some(Body::Full(bytes)) => bytes.len() >= self.min_size,
+                Some(Body::File { len, .. }) => *len >= self.min_size as u64,
+                Some(Body::Stream(_)) => true,
+                None => false,
+            }
+    }
+
+    // 内容类型是否适合压缩，不考虑状态码和响应体
+    fn compressible_type(&self, response: &HttpResponse) -> bool {
+        if response
+            .header("Cache-Control")
+            .is_some_and(|c| c.to_lowercase().contains("no-transform"))
+        {
+            return false;
+        }
+        let mime = response
+            .header("Content-Type")
+            .and_then(|t| t.split(';').next())
+            .map(|t| t.trim().to_lowercase())
+            .unwrap_or_default();
+        // 事件流压缩后需要等缓冲区满才发送，客户端会收不到及时的事件
+        !(COMPRESSED_TYPES.contains(&mime.as_str())
+            || mime == "text/event-stream"
+            || mime.starts_with("video/")
+            || mime.starts_with("audio/"))
+    }
+
+    // 响应是否随Accept-Encoding变化，206和304的完整内容可能被压缩，缓存也需要区分
+    fn varies(&self, response: &HttpResponse) -> bool {
+        match response.status() {
+            HttpStatus::PartialContent | HttpStatus::NotModified => {
+                response.header("Content-Encoding").is_none() && self.compressible_type(response)
+            }
+            _ => self.compressible(response),
+        }
+    }
+
+    fn encoder(&self, coding: &str) -> Encoder {
+        match coding {
+            "br" => Encoder::Brotli(Box::new(CompressorWriter::new(
+                Vec::new(),
+                4096,
+                self.brotli_level,
+                22,
+            ))),
+            "gzip" => Encoder::Gzip(GzEncoder::new(
+                Vec::new(),
+                flate2::Compression::new(self.gzip_level),
+            )),
+            _ => Encoder::Deflate(ZlibEncoder::new(
+                Vec::new(),
+                flate2::Compression::new(self.gzip_level),
+            )),
+        }
+    }
+}
+
+impl Middleware for Compression {
+    fn handle(&self, req: HttpRequest, next: Next) -> HttpResponse<'static> {
+        let accept_encoding = req.headers().get("accept-encoding").map(|h| h.to_string());
+        let mut response = next.run(req);
+        if self.varies(&response) {
+            add_vary(&mut response);
+        }
+        if !self.compressible(&response) {
+            return response;
+        }
+        let coding = match accept_encoding.as_deref().and_then(negotiate) {
+            Some(coding) => coding,
+            None => return response,
+        };
+
+        let encoder = self.encoder(coding);
+        match response.take_body() {
+            Some(Body::Full(bytes)) if bytes.len() > BLOCKING_SIZE => {
+                let (sender, compressed) = mpsc::channel(1);
+                tokio::task::spawn_blocking(move || {
+                    let _ = sender.blocking_send(encoder.encode(&bytes));
+                });
+                response.set_body(Some(Body::Stream(compressed)));
+            }
+            Some(Body::Full(bytes)) => {
+                match encoder.encode(&bytes) {
+                    Ok(compressed) => response.set_body(Some(Body::Full(compressed.into()))),
+                    // 压缩失败时发送原始内容
+                    Err(_) => {
+                        response.set_body(Some(Body::Full(bytes)));
+                        return response;
+                    }
+                }
+            }
+            // 文件在后台分块读取后按流式响应体压缩
+            Some(body @ (Body::Stream(_) | Body::File { .. })) => {
+                let mut receiver = body.into_stream();
+                let mut encoder = encoder;
+                // 每收到一块数据就压缩并刷新，保证客户端能及时收到
+                let (sender, compressed) = mpsc::channel(16);
+                tokio::spawn(async move {
+                    while let Some(chunk) = receiver.recv().await {
+                        // 读取或压缩失败时把错误传下去，让连接中止
+                        let data = chunk.and_then(|chunk| encoder.write(&chunk));
+                        let failed = data.is_err();
+                        if sender.send(data).await.is_err() || failed {
+                            return;
+                        }
+                    }
+                    let _ = sender.send(encoder.finish()).await;
+                });
+                response.set_body(Some(Body::Stream(compressed)));
+            }
+            None => return response,
+        }
+
+        response.set_header("Content-Encoding", coding);
+        // 压缩后的内容与原始内容不同，不能再使用强ETag和范围请求
+        if let Some(etag) = response.header("ETag") {
+            if !etag.starts_with("W/") {
+                let weak = format!("W/{}", etag);
+                response.set_header("ETag", weak);
+            }
+        }
+        response.remove_header("Accept-Ranges");
+        response
+    }
+}
+
+// 在已有的Vary中追加Accept-Encoding
+fn add_vary(response: &mut HttpResponse) {
+    let vary = match response.header("Vary") {
+        Some(vary)
+            if vary
+                .split(',')
+                .any(|v| v.trim().eq_ignore_ascii_case("accept-encoding") || v.trim() == "*") =>
+        {
+            return
+        }
+        Some(vary) => format!("{}, Accept-Encoding", vary),
+        None => String::from("Accept-Encoding"),
+    };
+    response.remove_header("Vary");
+    response.set_header("Vary", vary);
+}
+
+// 压缩器，每次写入后取出已经压缩好的数据
+enum Encoder {
+    Gzip(GzEncoder<Vec<u8>>),
+    Deflate(ZlibEncoder<Vec<u8>>),
+    Brotli(Box<CompressorWriter<Vec<u8>>>),
+}
+
+impl Encoder {
+    // 一次性压缩完整的内容
+    fn encode(mut self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match &mut self {
+            Encoder::Gzip(encoder) => encoder.write_all(data)?,
+            Encoder::Deflate(encoder) => encoder.write_all(data)?,
+            Encoder::Brotli(encoder) => encoder.write_all(data)?,
+        }
+        self.finish()
+    }
+
+    // 写入一块数据并刷新，返回目前为止压缩好的数据
+    fn write(&mut self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let output = match self {
+            Encoder::Gzip(encoder) => {
+                encoder.write_all(data)?;
+                encoder.flush()?;
+                encoder.get_mut()
+            }
+            Encoder::Deflate(encoder) => {
+                encoder.write_all(data)?;
+                encoder.flush()?;
+                encoder.get_mut()
+            }
+            Encoder::Brotli(encoder) => {
+                encoder.write_all(data)?;
+                encoder.flush()?;
+                encoder.get_mut()
+            }
+        };
+        Ok(std::mem::take(output))
+    }
+
+    fn finish(self) -> io::Result<Vec<u8>> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.finish(),
+            Encoder::Deflate(encoder) => encoder.finish(),
+            Encoder::Brotli(encoder) => Ok(encoder.into_inner()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::Router;
+    use flate2::read::GzDecoder;
+    use std::collections::BTreeMap;
+    use std::io::Read;
+
+    fn response(content_type: &str, body: Vec<u8>) -> HttpResponse<'static> {
+        let mut response =
+            HttpResponse::new(HttpStatus::Ok, None::<BTreeMap<&str, &str>>, Some(body));
+        response.set_header("Content-Type", content_type.to_string());
+        response
+    }
+
+    // 处理器返回的响应经过压缩中间件
+    fn compress(
+        accept_encoding: &str,
+        make: impl Fn() -> HttpResponse<'static> + Send + Sync + 'static,
+    ) -> HttpResponse<'static> {
+        let router = Router::new()
+            .middleware(Compression::new().min_size(16))
+            .fallback(move |_: &HttpRequest| make());
+        let head = format!(
+            "GET / HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: {}\r\n",
+            accept_encoding
+        );
+        router.handle(HttpRequest::from(&head, Vec::new(), "127.0.0.1").unwrap())
+    }
+
+    fn gunzip(data: &[u8]) -> Vec<u8> {
+        let mut output = Vec::new();
+        GzDecoder::new(data).read_to_end(&mut output).unwrap();
+        output
+    }
+
+    #[test]
+    fn negotiate_by_quality() {
+        assert_eq!(negotiate("gzip, deflate, br"), Some("br"));
+        assert_eq!(negotiate("gzip;q=0.8, br;q=0.5"), Some("gzip"));
+        assert_eq!(negotiate("deflate;q=1, gzip;q=1"), Some("gzip"));
+        assert_eq!(negotiate("x-gzip"), Some("gzip"));
+        assert_eq!(negotiate("GZIP; q=0.5"), Some("gzip"));
+        // *匹配没有明确列出的编码
+        assert_eq!(negotiate("*"), Some("br"));
+        assert_eq!(negotiate("br;q=0, *;q=0.1"), Some("gzip"));
+        // q=0表示不接受
+        assert_eq!(negotiate("gzip;q=0"), None);
+        assert_eq!(negotiate("*;q=0"), None);
+        assert_eq!(negotiate("identity"), None);
+        assert_eq!(negotiate(""), None);
+    }
+
+    #[test]
+    fn accepts_coding() {
+        assert!(accepts(Some("gzip, br"), "br"));
+        assert!(accepts(Some("x-gzip"), "gzip"));
+        assert!(!accepts(Some("br;q=0"), "br"));
+        assert!(!accepts(None, "gzip"));
+    }
+
+    #[test]
+    fn vary_header() {
+        let mut r = response("text/plain", Vec::new());
+        add_vary(&mut r);
+        assert_eq!(r.header("Vary"), Some("Accept-Encoding"));
+        add_vary(&mut r);
+        assert_eq!(r.header("Vary"), Some("Accept-Encoding"));
+
+        let mut r = response("text/plain", Vec::new());
+        r.set_header("Vary", "Origin");
+        add_vary(&mut r);
+        assert_eq!(r.header("Vary"), Some("Origin, Accept-Encoding"));
+
+        let mut r = response("text/plain", Vec::new());
+        r.set_header("Vary", "*");
+        add_vary(&mut r);
+        assert_eq!(r.header("Vary"), Some("*"));
+    }
+
+    #[test]
+    fn compress_and_weaken_etag() {
+        let text = "hello compression ".repeat(10);
+        let body = text.clone().into_bytes();
+        let r = compress("gzip", move || {
+            let mut r = response("text/plain; charset=utf-8", body.clone());
+            r.set_header("ETag", "\"v1\"");
+            r.set_header("Accept-Ranges", "bytes");
+            r
+        });
+        assert_eq!(r.header("Content-Encoding"), Some("gzip"));
+        assert_eq!(r.header("ETag"), Some("W/\"v1\""));
+        assert_eq!(r.header("Accept-Ranges"), None);
+        assert_eq!(r.header("Vary"), Some("Accept-Encoding"));
+        match r.body() {
+            Some(Body::Full(bytes)) => assert_eq!(gunzip(bytes), text.as_bytes()),
+            body => panic!("unexpected body {:?}", body),
+        }
+
+        // 弱ETag保持不变
+        let r = compress("gzip", || {
+            let mut r = response("text/plain", vec![b'a'; 100]);
+            r.set_header("ETag", "W/\"v1\"");
+            r
+        });
+        assert_eq!(r.header("ETag"), Some("W/\"v1\""));
+    }
+
+    #[test]
+    fn skip_uncompressible() {
+        // 太小
+        let r = compress("gzip", || response("text/plain", vec![b'a'; 8]));
+        assert_eq!(r.header("Content-Encoding"), None);
+        // 已经压缩过的类型
+        let r = compress("gzip", || response("image/png", vec![b'a'; 100]));
+        assert_eq!(r.header("Content-Encoding"), None);
+        assert_eq!(r.header("Vary"), None);
+        // 事件流
+        let r = compress("gzip", || response("text/event-stream", vec![b'a'; 100]));
+        assert_eq!(r.header("Content-Encoding"), None);
+        // no-transform
+        let r = compress("gzip", || {
+            let mut r = response("text/plain", vec![b'a'; 100]);
+            r.set_header("Cache-Control", "no-transform");
+            r
+        });
+        assert_eq!(r.header("Content-Encoding"), None);
+        // 客户端不支持时仍然需要Vary
+        let r = compress("identity", || response("text/plain", vec![b'a'; 100]));
+        assert_eq!(r.header("Content-Encoding"), None);
+        assert_eq!(r.header("Vary"), Some("Accept-Encoding"));
+    }
+
+    #[tokio::test]
+    async fn compress_large_body_off_runtime() {
+        let body = vec![b'a'; BLOCKING_SIZE + 1];
+        let expected = body.clone();
+        let mut r = compress("gzip", move || response("text/plain", body.clone()));
+        assert_eq!(r.header("Content-Encoding"), Some("gzip"));
+        let mut receiver = match r.take_body() {
+            Some(Body::Stream(receiver)) => receiver,
+            body => panic!("unexpected body {:?}", body),
+        };
+        let mut compressed = Vec::new();
+        while let Some(chunk) = receiver.recv().await {
+            compressed.extend(chunk.unwrap());
+        }
+        assert_eq!(gunzip(&compressed), expected);
+    }
+}
@@ -0,0 +1,354 @@
+use crate::caching::EtagMode;
+use crate::error::{Fail, Result};
+use crate::handler::SymlinkPolicy;
+use crate::tls::{CertSource, ClientAuth};
+use std::fs;
+use std::path::PathBuf;
+
+// 启动配置，来自配置文件和命令行参数，命令行参数优先
+#[derive(Clone, Debug)]
+pub struct Config {
+    // 监听地址，可以有多个，支持 IP:端口、主机名:端口、unix:路径、fd:编号 和 systemd
+    pub addrs: Vec<String>,
+    // Unix套接字文件的权限
+    pub unix_mode: Option<u32>,
+    // 静态资源目录，靠前的优先
+    pub roots: Vec<PathBuf>,
+    // 虚拟主机，主机名和静态资源目录，没有匹配的主机使用roots
+    pub vhosts: Vec<(String, PathBuf)>,
+    // 重写规则文件
+    pub rewrite: Option<PathBuf>,
+    // 静态资源中的符号链接策略
+    pub symlinks: SymlinkPolicy,
+    // 是否允许访问.开头的文件
+    pub dotfiles: bool,
+    // 目录中没有index.html时是否显示目录列表
+    pub listing: bool,
+    // 是否使用 .br、.gz 预压缩文件
+    pub precompressed: bool,
+    // 自定义MIME类型，扩展名和类型
+    pub mime_types: Vec<(String, String)>,
+    // 没有扩展名的文件是否根据内容判断类型
+    pub sniff: bool,
+    // ETag生成方式
+    pub etag: EtagMode,
+    // 按路径设置的Cache-Control
+    pub cache_control: Vec<(String, String)>,
+    // 使用编译进可执行文件的资源
+    pub embedded: bool,
+    // 内嵌模式下改为读取磁盘上的资源目录，优先使用指定的目录
+    pub live: bool,
+    // 静态文件内存缓存的最大字节数，0表示不缓存
+    pub file_cache: usize,
+    // 不小于该大小的文件直接从文件发送
+    pub sendfile_threshold: usize,
+    // PEM格式的证书链，与私钥同时指定时使用HTTPS
+    pub tls_cert: Option<PathBuf>,
+    // PEM格式的私钥
+    pub tls_key: Option<PathBuf>,
+    // 按SNI主机名选择的证书
+    pub tls_sni: Vec<CertSource>,
+    // 验证客户端证书的CA证书
+    pub tls_client_ca: Option<PathBuf>,
+    // 客户端证书验证方式
+    pub tls_client_auth: ClientAuth,
+    // 是否支持HTTP/2
+    pub http2: bool,
+    // 启用HTTPS时重定向到HTTPS的明文HTTP监听地址
+    pub redirect_addr: Option<String>,
+    // 不重定向的路径前缀
+    pub redirect_exempt: Vec<String>,
+    // HTTPS响应中的Strict-Transport-Security
+    pub hsts: Option<String>,
+    // 发送PROXY协议头的负载均衡地址，支持CIDR
+    pub proxy_protocol: Vec<String>,
+    // 是否压缩响应
+    pub compress: bool,
+    // 小于该大小的响应不压缩
+    pub compress_min_size: usize,
+    // gzip和deflate的压缩级别
+    pub gzip_level: u32,
+    // brotli的压缩级别
+    pub brotli_level: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            addrs: Vec::new(),
+            unix_mode: None,
+            roots: Vec::new(),
+            vhosts: Vec::new(),
+            rewrite: None,
+            symlinks: SymlinkPolicy::WithinRoot,
+            dotfiles: false,
+            listing: false,
+            precompressed: true,
+            mime_types: Vec::new(),
+            sniff: false,
+            etag: EtagMode::Strong,
+            cache_control: Vec::new(),
+            embedded: false,
+            live: false,
+            file_cache: 0,
+            sendfile_threshold: 1 << 20,
+            tls_cert: None,
+            tls_key: None,
+            tls_sni: Vec::new(),
+            tls_client_ca: None,
+            tls_client_auth: ClientAuth::Required,
+            http2: true,
+            redirect_addr: None,
+            redirect_exempt: Vec::new(),
+            hsts: None,
+            proxy_protocol: Vec::new(),
+            compress: false,
+            compress_min_size: 1024,
+            gzip_level: 6,
+            brotli_level: 4,
+        }
+    }
+}
+
+impl Config {
+    // 解析命令行参数，--config指定的配置文件先加载
+    pub fn from_args<I: Iterator<Item = String>>(args: I) -> Result<Self> {
+        let args: Vec<String> = args.collect();
+        let mut config = Config::default();
+        if let Some(i) = args.iter().position(|a| a == "--config") {
+            let path = args
+                .get(i + 1)
+                .ok_or_else(|| Fail::new("--config 缺少参数"))?;
+            config.load_file(path)?;
+        }
+
+        let mut cli_roots = Vec::new();
+        let mut cli_addrs = Vec::new();
+        let mut iter = args.iter().skip(1);
+        while let Some(arg) = iter.next() {
+            let mut value = || {
+                iter.next()
+                    .ok_or_else(|| Fail::new(format!("{} 缺少参数", arg)))
+            };
+            match arg.as_str() {
+                "--config" => {
+                    value()?;
+                }
+                "--addr" => cli_addrs.push(value()?.to_string()),
+                "--unix-mode" => config.unix_mode = Some(parse_mode(value()?)?),
+                "--root" => cli_roots.push(PathBuf::from(value()?)),
+                "--vhost" => config.vhosts.push(parse_vhost(value()?)?),
+                "--rewrite" => config.rewrite = Some(PathBuf::from(value()?)),
+                "--symlinks" => config.symlinks = parse_symlinks(value()?)?,
+                "--dotfiles" => config.dotfiles = true,
+                "--listing" => config.listing = true,
+                "--no-precompressed" => config.precompressed = false,
+                "--mime" => config.mime_types.push(parse_mime(value()?)?),
+                "--sniff" => config.sniff = true,
+                "--etag" => config.etag = parse_etag(value()?)?,
+                "--cache" => config.cache_control.push(parse_cache(value()?)?),
+                "--embedded" => config.embedded = true,
+                "--live" => config.live = true,
+                "--file-cache" => config.file_cache = parse_size(value()?)?,
+                "--sendfile-threshold" => config.sendfile_threshold = parse_size(value()?)?,
+                "--tls-cert" => config.tls_cert = Some(PathBuf::from(value()?)),
+                "--tls-key" => config.tls_key = Some(PathBuf::from(value()?)),
+                "--tls-sni" => config.tls_sni.push(parse_sni(value()?)?),
+                "--tls-client-ca" => config.tls_client_ca = Some(PathBuf::from(value()?)),
+                "--tls-client-auth" => config.tls_client_auth = parse_client_auth(value()?)?,
+                "--no-http2" => config.http2 = false,
+                "--redirect-addr" => config.redirect_addr = Some(value()?.to_string()),
+                "--redirect-exempt" => config.redirect_exempt.push(value()?.to_string()),
+                "--hsts" => config.hsts = Some(value()?.to_string()),
+                "--proxy-protocol" => config.proxy_protocol.push(value()?.to_string()),
+                "--compress" => config.compress = true,
+                "--compress-min-size" => config.compress_min_size = parse_number(value()?)?,
+                "--gzip-level" => config.gzip_level = parse_level(value()?, 9)?,
+                "--brotli-level" => config.brotli_level = parse_level(value()?, 11)?,
+                _ => return Fail::from(format!("未知的参数: {}", arg)),
+            }
+        }
+        // 命令行中的目录覆盖配置文件中的目录
+        if !cli_roots.is_empty() {
+            config.roots = cli_roots;
+        }
+        if !cli_addrs.is_empty() {
+            config.addrs = cli_addrs;
+        }
+        if config.embedded && !cfg!(feature = "embed") {
+            return Fail::from("--embedded 需要启用embed特性编译");
+        }
+        // 内嵌模式下没有指定目录时，实时模式读取编译时的资源目录
+        if config.roots.is_empty() && !config.embedded {
+            config.roots.push(PathBuf::from("static"));
+        }
+        if config.addrs.is_empty() {
+            config.addrs.push(String::from("127.0.0.1:8080"));
+        }
+        Ok(config)
+    }
+
+    // 配置文件每行一个 key = value，#开头为注释，root、vhost和addr可以出现多次
+    fn load_file(&mut self, path: &str) -> Result<()> {
+        let content = fs::read_to_string(path)?;
+        for (i, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| Fail::new(format!("{} 第{}行: 格式错误", path, i + 1)))?;
+            let value = value.trim();
+            match key.trim() {
+                "addr" => self.addrs.push(value.to_string()),
+                "unix_mode" => self.unix_mode = Some(parse_mode(value)?),
+                "root" => self.roots.push(PathBuf::from(value)),
+                "vhost" => self.vhosts.push(parse_vhost(value)?),
+                "rewrite" => self.rewrite = Some(PathBuf::from(value)),
+                "symlinks" => self.symlinks = parse_symlinks(value)?,
+                "dotfiles" => self.dotfiles = parse_bool(value)?,
+                "listing" => self.listing = parse_bool(value)?,
+                "precompressed" => self.precompressed = parse_bool(value)?,
+                "mime" => self.mime_types.push(parse_mime(value)?),
+                "sniff" => self.sniff = parse_bool(value)?,
+                "etag" => self.etag = parse_etag(value)?,
+                "cache" => self.cache_control.push(parse_cache(value)?),
+                "embedded" => self.embedded = parse_bool(value)?,
+                "live" => self.live = parse_bool(value)?,
+                "file_cache" => self.file_cache = parse_size(value)?,
+                "sendfile_threshold" => self.sendfile_threshold = parse_size(value)?,
+                "tls_cert" => self.tls_cert = Some(PathBuf::from(value)),
+                "tls_key" => self.tls_key = Some(PathBuf::from(value)),
+                "tls_sni" => self.tls_sni.push(parse_sni(value)?),
+                "tls_client_ca" => self.tls_client_ca = Some(PathBuf::from(value)),
+                "tls_client_auth" => self.tls_client_auth = parse_client_auth(value)?,
+                "http2" => self.http2 = parse_bool(value)?,
+                "redirect_addr" => self.redirect_addr = Some(value.to_string()),
+                "redirect_exempt" => self.redirect_exempt.push(value.to_string()),
+                "hsts" => self.hsts = Some(value.to_string()),
+                "proxy_protocol" => self.proxy_protocol.push(value.to_string()),
+                "compress" => self.compress = parse_bool(value)?,
+                "compress_min_size" => self.compress_min_size = parse_number(value)?,
+                "gzip_level" => self.gzip_level = parse_level(value, 9)?,
+                "brotli_level" => self.brotli_level = parse_level(value, 11)?,
+                key => return Fail::from(format!("{} 第{}行: 未知的配置项 {}", path, i + 1, key)),
+            }
+        }
+        Ok(())
+    }
+}
+
+// 格式为 主机名 目录，如 *.example.com /srv/example
+fn parse_vhost(value: &str) -> Result<(String, PathBuf)> {
+    match value.trim().split_once(char::is_whitespace) {
+        Some((host, root)) => Ok((host.to_string(), PathBuf::from(root.trim()))),
+        None => Fail::from(format!("无效的虚拟主机配置: {}", value)),
+    }
+}
+
+fn parse_symlinks(value: &str) -> Result<SymlinkPolicy> {
+    match value {
+        "follow" => Ok(SymlinkPolicy::Follow),
+        "within_root" => Ok(SymlinkPolicy::WithinRoot),
+        "deny" => Ok(SymlinkPolicy::Deny),
+        _ => Fail::from(format!("未知的符号链接策略: {}", value)),
+    }
+}
+
+// 格式为 扩展名:类型，如 mjs:text/javascript
+fn parse_mime(value: &str) -> Result<(String, String)> {
+    match value.split_once(':') {
+        Some((extension, mime)) => Ok((extension.trim().to_string(), mime.trim().to_string())),
+        None => Fail::from(format!("无效的MIME类型配置: {}", value)),
+    }
+}
+
+fn parse_etag(value: &str) -> Result<EtagMode> {
+    match value {
+        "strong" => Ok(EtagMode::Strong),
+        "weak" => Ok(EtagMode::Weak),
+        "off" => Ok(EtagMode::Off),
+        _ => Fail::from(format!("未知的ETag方式: {}", value)),
+    }
+}
+
+// 格式为 路径 Cache-Control，如 *.css public, max-age=86400
+fn parse_cache(value: &str) -> Result<(String, String)> {
+    match value.trim().split_once(char::is_whitespace) {
+        Some((pattern, cache_control)) => {
+            Ok((pattern.to_string(), cache_control.trim().to_string()))
+        }
+        None => Fail::from(format!("无效的缓存配置: {}", value)),
+    }
+}
+
+// 格式为 主机名 证书 私钥，如 *.example.com cert.pem key.pem
+fn parse_sni(value: &str) -> Result<CertSource> {
+    match value.split_whitespace().collect::<Vec<_>>()[..] {
+        [host, cert, key] => Ok(CertSource {
+            host: Some(host.to_string()),
+            cert: PathBuf::from(cert),
+            key: PathBuf::from(key),
+        }),
+        _ => Fail::from(format!("无效的SNI证书配置: {}", value)),
+    }
+}
+
+fn parse_client_auth(value: &str) -> Result<ClientAuth> {
+    match value {
+        "required" => Ok(ClientAuth::Required),
+        "optional" => Ok(ClientAuth::Optional),
+        _ => Fail::from(format!("未知的客户端证书验证方式: {}", value)),
+    }
+}
+
+fn parse_number(value: &str) -> Result<usize> {
+    match value.parse() {
+        Ok(number) => Ok(number),
+        Err(_) => Fail::from(format!("无效的数字: {}", value)),
+    }
+}
+
+// 字节数，支持K、M、G后缀，如 64M
+fn parse_size(value: &str) -> Result<usize> {
+    let (number, unit) = match value.to_uppercase().chars().last() {
+        Some('K') => (&value[..value.len() - 1], 1 << 10),
+        Some('M') => (&value[..value.len() - 1], 1 << 20),
+        Some('G') => (&value[..value.len() - 1], 1 << 30),
+        _ => (value, 1),
+    };
+    match number
+        .trim()
+        .parse::<usize>()
+        .map(|number| number.checked_mul(unit))
+    {
+        Ok(Some(size)) => Ok(size),
+        Ok(None) => Fail::from(format!("大小超出范围: {}", value)),
+        Err(_) => Fail::from(format!("无效的大小: {}", value)),
+    }
+}
+
+// 压缩级别，范围 0-max
+fn parse_level(value: &str, max: u32) -> Result<u32> {
+    match value.parse::<u32>() {
+        Ok(level) if level <= max => Ok(level),
+        _ => Fail::from(format!("无效的压缩级别: {}，范围 0-{}", value, max)),
+    }
+}
+
+// 八进制的文件权限，如 660
+fn parse_mode(value: &str) -> Result<u32> {
+    match u32::from_str_radix(value.trim_start_matches("0o"), 8) {
+        Ok(mode) if mode <= 0o777 => Ok(mode),
+        _ => Fail::from(format!("无效的文件权限: {}", value)),
+    }
+}
+
+fn parse_bool(value: &str) -> Result<bool> {
+    match value {
+        "true" | "on" | "yes" => Ok(true),
+        "false" | "off" | "no" => Ok(false),
+        _ => Fail::from(format!("无效的布尔值: {}", value)),
+    }
+}
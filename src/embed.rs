@@ -0,0 +1,47 @@
+// 编译进可执行文件的静态资源
+pub struct Asset {
+    // 以/开头的请求路径
+    pub path: &'static str,
+    pub contents: &'static [u8],
+    pub mime: &'static str,
+    // 根据内容生成的强ETag
+    pub etag: &'static str,
+    // 编译时文件的修改时间，单位为秒
+    pub modified: u64,
+    pub gzip: Option<&'static [u8]>,
+    pub br: Option<&'static [u8]>,
+}
+
+// 按路径排序，由build.rs生成
+#[cfg(feature = "embed")]
+include!(concat!(env!("OUT_DIR"), "/embedded.rs"));
+
+#[cfg(not(feature = "embed"))]
+pub static ASSETS: &[Asset] = &[];
+
+// 编译时的资源目录
+pub const EMBED_DIR: &str = env!("EMBED_DIR");
+
+impl Asset {
+    // 预压缩的内容
+    pub fn encoded(&self, coding: &str) -> Option<&'static [u8]> {
+        match coding {
+            "br" => self.br,
+            "gzip" => self.gzip,
+            _ => None,
+        }
+    }
+}
+
+pub fn find(path: &str) -> Option<&'static Asset> {
+    ASSETS
+        .binary_search_by(|asset| asset.path.cmp(path))
+        .ok()
+        .map(|i| &ASSETS[i])
+}
+
+// 是否存在以该路径为目录的资源
+pub fn is_dir(path: &str) -> bool {
+    let prefix = format!("{}/", path.trim_end_matches('/'));
+    ASSETS.iter().any(|asset| asset.path.starts_with(&prefix))
+}
@@ -0,0 +1,709 @@
+use crate::constant;
+use crate::handler::Handler;
+use crate::request::HttpRequest;
+use crate::response::{HttpResponse, HttpStatus};
+use crate::utils::url_decode;
+use serde::de::value::{MapDeserializer, SeqDeserializer, StrDeserializer};
+use serde::de::{self, DeserializeOwned, Deserializer, IntoDeserializer, Visitor};
+use serde::forward_to_deserialize_any;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::marker::PhantomData;
+use std::net::IpAddr;
+
+// 提取失败的原因
+#[derive(Debug)]
+pub struct Rejection {
+    status: HttpStatus,
+    message: String,
+}
+
+impl Rejection {
+    pub fn bad_request<E: Display>(source: &str, err: E) -> Self {
+        Self {
+            status: HttpStatus::BadRequest,
+            message: format!("{}: {}", source, err),
+        }
+    }
+    pub fn internal<E: Display>(source: &str, err: E) -> Self {
+        Self {
+            status: HttpStatus::InternalServerError,
+            message: format!("{}: {}", source, err),
+        }
+    }
+}
+
+// 从请求中提取处理器参数
+pub trait FromRequest: Sized {
+    fn from_request(req: &HttpRequest) -> Result<Self, Rejection>;
+}
+
+// 路径参数，如 /users/:id
+#[derive(Debug)]
+pub struct Path<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest for Path<T> {
+    fn from_request(req: &HttpRequest) -> Result<Self, Rejection> {
+        from_fields(req.params())
+            .map(Path)
+            .map_err(|e| Rejection::bad_request("路径参数", e))
+    }
+}
+
+// 查询参数
+#[derive(Debug)]
+pub struct Query<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest for Query<T> {
+    fn from_request(req: &HttpRequest) -> Result<Self, Rejection> {
+        let fields = req
+            .query_pairs()
+            .map_err(|e| Rejection::bad_request("查询参数", e))?
+            .iter()
+            .filter(|(k, _)| !k.is_empty())
+            .map(|(k, v)| (url_decode(k, true), url_decode(v, true)))
+            .collect();
+        from_fields(&fields)
+            .map(Query)
+            .map_err(|e| Rejection::bad_request("查询参数", e))
+    }
+}
+
+// 表单，支持urlencoded和multipart
+#[derive(Debug)]
+pub struct Form<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest for Form<T> {
+    fn from_request(req: &HttpRequest) -> Result<Self, Rejection> {
+        let content_type = req.headers().get("content-type").copied().unwrap_or("");
+        let form = || {
+            req.form_fields()
+                .map_err(|e| Rejection::bad_request("表单", e))
+                .map(|form| {
+                    form.into_iter()
+                        .map(|(k, v)| (k, String::from_utf8_lossy(&v).to_string()))
+                })
+        };
+        // 字段名保留大小写，与结构体的字段对应
+        let fields = if content_type.starts_with(constant::APPLICATION_X_WWW_FORM_URLENCODED) {
+            form()?
+                .filter(|(k, _)| !k.is_empty())
+                .map(|(k, v)| (url_decode(&k, true), url_decode(&v, true)))
+                .collect()
+        } else if content_type.starts_with(constant::MULTIPART_FORM_DATA) {
+            form()?.collect()
+        } else {
+            return Err(Rejection::bad_request("表单", "不支持的Content-Type"));
+        };
+        from_fields(&fields)
+            .map(Form)
+            .map_err(|e| Rejection::bad_request("表单", e))
+    }
+}
+
+// JSON请求体
+#[derive(Debug)]
+pub struct Json<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest for Json<T> {
+    fn from_request(req: &HttpRequest) -> Result<Self, Rejection> {
+        let content_type = req.headers().get("content-type").copied().unwrap_or("");
+        if !content_type.starts_with(constant::APPLICATION_JSON) {
+            return Err(Rejection::bad_request(
+                "JSON",
+                "Content-Type不是application/json",
+            ));
+        }
+        serde_json::from_slice(req.raw_body())
+            .map(Json)
+            .map_err(|e| Rejection::bad_request("JSON", e))
+    }
+}
+
+// 请求头，字段名为小写的请求头名称
+#[derive(Debug)]
+pub struct Headers<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest for Headers<T> {
+    fn from_request(req: &HttpRequest) -> Result<Self, Rejection> {
+        let fields = req
+            .headers()
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        from_fields(&fields)
+            .map(Headers)
+            .map_err(|e| Rejection::bad_request("请求头", e))
+    }
+}
+
+// Cookie
+#[derive(Debug)]
+pub struct Cookies<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest for Cookies<T> {
+    fn from_request(req: &HttpRequest) -> Result<Self, Rejection> {
+        let mut fields = BTreeMap::new();
+        if let Some(cookie) = req.headers().get("cookie") {
+            for pair in cookie.split(';') {
+                let mut kv = pair.splitn(2, '=');
+                if let (Some(k), Some(v)) = (kv.next(), kv.next()) {
+                    fields.insert(k.trim().to_string(), url_decode(v.trim(), false));
+                }
+            }
+        }
+        from_fields(&fields)
+            .map(Cookies)
+            .map_err(|e| Rejection::bad_request("Cookie", e))
+    }
+}
+
+// 客户端IP
+#[derive(Debug)]
+pub struct ClientIp(pub IpAddr);
+
+impl FromRequest for ClientIp {
+    fn from_request(req: &HttpRequest) -> Result<Self, Rejection> {
+        if req.peer_addr().is_some_and(|peer| peer.is_unix()) {
+            return Err(Rejection::internal("客户端IP", "Unix套接字的连接没有IP"));
+        }
+        req.ip()
+            .parse()
+            .map(ClientIp)
+            .map_err(|e| Rejection::internal("客户端IP", e))
+    }
+}
+
+// 路由共享的状态，通过Router::state注册
+#[derive(Debug)]
+pub struct State<T>(pub T);
+
+impl<T: Clone + 'static> FromRequest for State<T> {
+    fn from_request(req: &HttpRequest) -> Result<Self, Rejection> {
+        req.extension::<T>()
+            .cloned()
+            .map(State)
+            .ok_or_else(|| Rejection::internal("State", "路由中没有注册该类型的状态"))
+    }
+}
+
+// 把参数为提取器的函数包装为handler
+pub struct Extract<F, T> {
+    f: F,
+    _marker: PhantomData<fn() -> T>,
+}
+
+pub fn extract<F, T>(f: F) -> Extract<F, T>
+where
+    Extract<F, T>: Handler,
+{
+    Extract {
+        f,
+        _marker: PhantomData,
+    }
+}
+
+// 所有提取失败的参数合并为一个400响应
+fn reject(rejections: Vec<Rejection>) -> HttpResponse<'static> {
+    let status = if rejections
+        .iter()
+        .any(|r| r.status == HttpStatus::InternalServerError)
+    {
+        HttpStatus::InternalServerError
+    } else {
+        HttpStatus::BadRequest
+    };
+    let message: Vec<String> = rejections.into_iter().map(|r| r.message).collect();
+    let mut headers = BTreeMap::new();
+    headers.insert("Content-Type", constant::TEXT_PLAIN_UTF8);
+    HttpResponse::new(status, Some(headers), Some(message.join("\n").into_bytes()))
+}
+
+macro_rules! impl_extract {
+    ($($t:ident),*) => {
+        #[allow(non_snake_case)]
+        impl<F, $($t,)*> Handler for Extract<F, ($($t,)*)>
+        where
+            F: Fn($($t),*) -> HttpResponse<'static> + Send + Sync,
+            $($t: FromRequest,)*
+        {
+            fn handle(&self, req: &HttpRequest) -> HttpResponse<'static> {
+                let mut rejections = Vec::new();
+                $(
+                    let $t = match $t::from_request(req) {
+                        Ok(v) => Some(v),
+                        Err(e) => {
+                            rejections.push(e);
+                            None
+                        }
+                    };
+                )*
+                match ($($t,)*) {
+                    ($(Some($t),)*) => (self.f)($($t),*),
+                    _ => reject(rejections),
+                }
+            }
+        }
+    };
+}
+
+impl_extract!(T1);
+impl_extract!(T1, T2);
+impl_extract!(T1, T2, T3);
+impl_extract!(T1, T2, T3, T4);
+impl_extract!(T1, T2, T3, T4, T5);
+impl_extract!(T1, T2, T3, T4, T5, T6);
+impl_extract!(T1, T2, T3, T4, T5, T6, T7);
+impl_extract!(T1, T2, T3, T4, T5, T6, T7, T8);
+
+// 字段解析错误，记录出错的字段名
+#[derive(Debug)]
+pub struct FieldError {
+    field: Option<String>,
+    message: String,
+}
+
+impl FieldError {
+    fn with_field(mut self, field: &str) -> Self {
+        if self.field.is_none() {
+            self.field = Some(field.to_string());
+        }
+        self
+    }
+}
+
+impl std::error::Error for FieldError {}
+
+impl Display for FieldError {
+    fn fmt(&self, formatter: &mut Formatter) -> FmtResult {
+        match &self.field {
+            Some(field) => write!(formatter, "字段 `{}` 解析失败: {}", field, self.message),
+            None => write!(formatter, "{}", self.message),
+        }
+    }
+}
+
+impl de::Error for FieldError {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self {
+            field: None,
+            message: msg.to_string(),
+        }
+    }
+}
+
+// 把字符串键值对反序列化为T
+fn from_fields<T: DeserializeOwned>(fields: &BTreeMap<String, String>) -> Result<T, FieldError> {
+    T::deserialize(Fields(fields))
+}
+
+struct Fields<'a>(&'a BTreeMap<String, String>);
+
+impl<'a> Fields<'a> {
+    // 只有一个字段时可以直接反序列化为基本类型，如 Path<u32>
+    fn single(&self) -> Result<FieldValue<'a>, FieldError> {
+        let mut iter = self.0.iter();
+        match (iter.next(), iter.next()) {
+            (Some((key, value)), None) => Ok(FieldValue { key, value }),
+            _ => Err(de::Error::custom(format!(
+                "需要1个参数，实际有{}个",
+                self.0.len()
+            ))),
+        }
+    }
+}
+
+macro_rules! forward_to_single {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FieldError> {
+                self.single()?.$method(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de, 'a> Deserializer<'de> for Fields<'a> {
+    type Error = FieldError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FieldError> {
+        let mut map = MapDeserializer::new(
+            self.0
+                .iter()
+                .map(|(key, value)| (key.as_str(), FieldValue { key, value })),
+        );
+        let value = visitor.visit_map(&mut map)?;
+        map.end()?;
+        Ok(value)
+    }
+
+    forward_to_single! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_f32
+        deserialize_f64 deserialize_char deserialize_str deserialize_string
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+// 单个字段的值
+struct FieldValue<'a> {
+    key: &'a str,
+    value: &'a str,
+}
+
+impl<'de, 'a> IntoDeserializer<'de, FieldError> for FieldValue<'a> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+macro_rules! deserialize_parse {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FieldError> {
+                match self.value.parse() {
+                    Ok(v) => visitor.$visit(v).map_err(|e: FieldError| e.with_field(self.key)),
+                    Err(e) => Err(FieldError {
+                        field: Some(self.key.to_string()),
+                        message: format!("{} ({:?})", e, self.value),
+                    }),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de, 'a> Deserializer<'de> for FieldValue<'a> {
+    type Error = FieldError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FieldError> {
+        visitor
+            .visit_str(self.value)
+            .map_err(|e: FieldError| e.with_field(self.key))
+    }
+
+    deserialize_parse! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    // 空值视为None
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FieldError> {
+        if self.value.is_empty() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    // 逗号分隔的列表
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FieldError> {
+        let key = self.key;
+        let values = self
+            .value
+            .split(',')
+            .filter(|v| !v.is_empty())
+            .map(|value| FieldValue { key, value });
+        let mut seq = SeqDeserializer::new(values);
+        let value = visitor.visit_seq(&mut seq)?;
+        seq.end().map_err(|e| e.with_field(key))?;
+        Ok(value)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, FieldError> {
+        StrDeserializer::<FieldError>::new(self.value)
+            .deserialize_enum(name, variants, visitor)
+            .map_err(|e: FieldError| e.with_field(self.key))
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, FieldError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 str string bytes byte_buf unit unit_struct tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::Body;
+    use serde::Deserialize;
+    use std::sync::Arc;
+
+    fn request<'a>(head: &'a str, body: &str) -> HttpRequest<'a> {
+        HttpRequest::from(head, body.as_bytes().to_vec(), "192.0.2.1").unwrap()
+    }
+
+    fn message(rejection: Rejection) -> String {
+        rejection.message
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct User {
+        id: u32,
+        name: String,
+    }
+
+    #[test]
+    fn path_params() {
+        let head = "GET /users/7/a%20b HTTP/1.1\r\n";
+        let mut req = request(head, "");
+        req.params_mut().insert("id".to_string(), "7".to_string());
+        req.params_mut()
+            .insert("name".to_string(), "a b".to_string());
+        let Path(user) = Path::<User>::from_request(&req).unwrap();
+        assert_eq!(
+            user,
+            User {
+                id: 7,
+                name: "a b".to_string()
+            }
+        );
+
+        let mut req = request(head, "");
+        req.params_mut().insert("id".to_string(), "7".to_string());
+        assert_eq!(Path::<u32>::from_request(&req).unwrap().0, 7);
+    }
+
+    #[test]
+    fn path_rejections() {
+        let head = "GET /users/x HTTP/1.1\r\n";
+        let mut req = request(head, "");
+        req.params_mut().insert("id".to_string(), "x".to_string());
+        req.params_mut().insert("name".to_string(), "a".to_string());
+        let err = message(Path::<User>::from_request(&req).unwrap_err());
+        assert!(err.starts_with("路径参数: 字段 `id` 解析失败"), "{}", err);
+        // 多个参数不能提取为单个值
+        let err = message(Path::<u32>::from_request(&req).unwrap_err());
+        assert!(err.contains("需要1个参数，实际有2个"), "{}", err);
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Search {
+        user_id: u32,
+        keyword: String,
+        page: Option<u32>,
+        tags: Vec<String>,
+    }
+
+    #[test]
+    fn query_params() {
+        let req = request(
+            "GET /search?userId=5&keyword=a+b%26c&page=&tags=x,y HTTP/1.1\r\n",
+            "",
+        );
+        let Query(search) = Query::<Search>::from_request(&req).unwrap();
+        assert_eq!(search.user_id, 5);
+        assert_eq!(search.keyword, "a b&c");
+        assert_eq!(search.page, None);
+        assert_eq!(search.tags, ["x", "y"]);
+    }
+
+    #[test]
+    fn query_rejections() {
+        let req = request("GET /search?userId=5 HTTP/1.1\r\n", "");
+        let err = message(Query::<Search>::from_request(&req).unwrap_err());
+        assert!(err.contains("missing field `keyword`"), "{}", err);
+        let req = request("GET /search?userId=x&keyword=a&tags= HTTP/1.1\r\n", "");
+        let err = message(Query::<Search>::from_request(&req).unwrap_err());
+        assert!(err.contains("字段 `userId` 解析失败"), "{}", err);
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Login {
+        user_name: String,
+        remember: bool,
+    }
+
+    #[test]
+    fn urlencoded_form() {
+        let head = "POST /login HTTP/1.1\r\nContent-Type: application/x-www-form-urlencoded\r\n";
+        let req = request(head, "userName=a%20b&remember=true");
+        let Form(login) = Form::<Login>::from_request(&req).unwrap();
+        assert_eq!(login.user_name, "a b");
+        assert!(login.remember);
+    }
+
+    #[test]
+    fn multipart_form() {
+        let head = "POST /login HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=XyZ\r\n";
+        let body = "--XyZ\r\nContent-Disposition: form-data; name=\"userName\"\r\n\r\nalice\r\n\
+                    --XyZ\r\nContent-Disposition: form-data; name=\"remember\"\r\n\r\nfalse\r\n\
+                    --XyZ--\r\n";
+        let Form(login) = Form::<Login>::from_request(&request(head, body)).unwrap();
+        assert_eq!(login.user_name, "alice");
+        assert!(!login.remember);
+    }
+
+    #[test]
+    fn form_rejections() {
+        let req = request(
+            "POST /login HTTP/1.1\r\nContent-Type: text/plain\r\n",
+            "a=1",
+        );
+        let err = message(Form::<Login>::from_request(&req).unwrap_err());
+        assert_eq!(err, "表单: 不支持的Content-Type");
+        let head = "POST /login HTTP/1.1\r\nContent-Type: application/x-www-form-urlencoded\r\n";
+        let req = request(head, "userName=a&remember=maybe");
+        let err = message(Form::<Login>::from_request(&req).unwrap_err());
+        assert!(err.contains("字段 `remember` 解析失败"), "{}", err);
+    }
+
+    #[test]
+    fn json_body() {
+        let head = "POST /users HTTP/1.1\r\nContent-Type: application/json; charset=utf-8\r\n";
+        let req = request(head, r#"{"id": 1, "name": "bob"}"#);
+        let Json(user) = Json::<User>::from_request(&req).unwrap();
+        assert_eq!(
+            user,
+            User {
+                id: 1,
+                name: "bob".to_string()
+            }
+        );
+
+        let req = request(head, r#"{"id": "1"}"#);
+        assert!(message(Json::<User>::from_request(&req).unwrap_err()).starts_with("JSON: "));
+        let req = request("POST /users HTTP/1.1\r\n", r#"{"id": 1, "name": "bob"}"#);
+        let err = message(Json::<User>::from_request(&req).unwrap_err());
+        assert_eq!(err, "JSON: Content-Type不是application/json");
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Auth {
+        #[serde(rename = "x-token")]
+        token: String,
+    }
+
+    #[test]
+    fn headers() {
+        let req = request("GET / HTTP/1.1\r\nX-Token: secret\r\nHost: a\r\n", "");
+        assert_eq!(
+            Headers::<Auth>::from_request(&req).unwrap().0.token,
+            "secret"
+        );
+        let req = request("GET / HTTP/1.1\r\nHost: a\r\n", "");
+        let err = message(Headers::<Auth>::from_request(&req).unwrap_err());
+        assert!(err.contains("missing field `x-token`"), "{}", err);
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Session {
+        session: String,
+        theme: Option<String>,
+    }
+
+    #[test]
+    fn cookies() {
+        let req = request("GET / HTTP/1.1\r\nCookie: session=a%3Db; other=1\r\n", "");
+        let Cookies(session) = Cookies::<Session>::from_request(&req).unwrap();
+        assert_eq!(session.session, "a=b");
+        assert_eq!(session.theme, None);
+        let req = request("GET / HTTP/1.1\r\n", "");
+        let err = message(Cookies::<Session>::from_request(&req).unwrap_err());
+        assert!(err.contains("missing field `session`"), "{}", err);
+    }
+
+    #[test]
+    fn client_ip() {
+        let req = request("GET / HTTP/1.1\r\n", "");
+        assert_eq!(
+            ClientIp::from_request(&req).unwrap().0,
+            "192.0.2.1".parse::<IpAddr>().unwrap()
+        );
+        #[cfg(unix)]
+        {
+            let mut req = HttpRequest::from("GET / HTTP/1.1\r\n", Vec::new(), "unix").unwrap();
+            req.insert_extension(Arc::new(crate::listener::PeerAddr::Unix(None)));
+            let rejection = ClientIp::from_request(&req).unwrap_err();
+            assert_eq!(rejection.status, HttpStatus::InternalServerError);
+        }
+    }
+
+    #[test]
+    fn state() {
+        let mut req = request("GET / HTTP/1.1\r\n", "");
+        assert_eq!(
+            State::<u32>::from_request(&req).unwrap_err().status,
+            HttpStatus::InternalServerError
+        );
+        req.insert_extension(Arc::new(42u32));
+        assert_eq!(State::<u32>::from_request(&req).unwrap().0, 42);
+    }
+
+    fn body(response: &HttpResponse) -> String {
+        match response.body() {
+            Some(Body::Full(bytes)) => String::from_utf8_lossy(bytes).to_string(),
+            _ => String::new(),
+        }
+    }
+
+    #[test]
+    fn combined_rejections() {
+        let handler = extract(|_: Query<Search>, _: Json<User>| {
+            HttpResponse::new(HttpStatus::Ok, None::<BTreeMap<&str, &str>>, None)
+        });
+        let req = request("GET / HTTP/1.1\r\n", "");
+        let response = handler.handle(&req);
+        assert_eq!(*response.status(), HttpStatus::BadRequest);
+        assert_eq!(
+            response.header("Content-Type"),
+            Some(constant::TEXT_PLAIN_UTF8)
+        );
+        // 所有失败的参数都会列出
+        let lines: Vec<String> = body(&response).lines().map(str::to_string).collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("查询参数: "));
+        assert!(lines[1].starts_with("JSON: "));
+
+        // 有服务端错误时返回500
+        let handler = extract(|_: Query<Search>, _: State<u32>| {
+            HttpResponse::new(HttpStatus::Ok, None::<BTreeMap<&str, &str>>, None)
+        });
+        let response = handler.handle(&req);
+        assert_eq!(*response.status(), HttpStatus::InternalServerError);
+
+        let handler = extract(|Path(id): Path<u32>| {
+            HttpResponse::new(
+                HttpStatus::Ok,
+                None::<BTreeMap<&str, &str>>,
+                Some(id.to_string().into_bytes()),
+            )
+        });
+        let mut req = request("GET /3 HTTP/1.1\r\n", "");
+        req.params_mut().insert("id".to_string(), "3".to_string());
+        let response = handler.handle(&req);
+        assert_eq!(*response.status(), HttpStatus::Ok);
+        assert_eq!(body(&response), "3");
+    }
+}
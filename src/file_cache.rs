@@ -0,0 +1,210 @@
+use bytes::Bytes;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, Metadata};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+// 缓存的文件，大小或修改时间变化时失效
+// 内容共享给各个响应，命中时不复制
+struct Entry {
+    contents: Bytes,
+    len: u64,
+    modified: Option<SystemTime>,
+    // 最近一次使用的序号
+    used: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<PathBuf, Entry>,
+    // 按使用顺序排列，最前面的最久没有使用
+    order: BTreeMap<u64, PathBuf>,
+    size: usize,
+    tick: u64,
+}
+
+impl Inner {
+    fn remove(&mut self, path: &Path) {
+        if let Some(entry) = self.entries.remove(path) {
+            self.order.remove(&entry.used);
+            self.size -= entry.contents.len();
+        }
+    }
+}
+
+// 按字节数限制大小的LRU文件缓存
+pub struct FileCache {
+    capacity: usize,
+    inner: Mutex<Inner>,
+}
+
+impl FileCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    // 超过容量1/8的文件不缓存，避免一个大文件挤掉所有缓存
+    fn cacheable(&self, len: u64) -> bool {
+        len <= (self.capacity / 8) as u64
+    }
+
+    // 读取文件，metadata与缓存一致时直接返回缓存的内容
+    pub fn read(&self, path: &Path, metadata: &Metadata) -> io::Result<Bytes> {
+        let modified = metadata.modified().ok();
+        if !self.cacheable(metadata.len()) {
+            return fs::read(path).map(Bytes::from);
+        }
+        if let Ok(mut inner) = self.inner.lock() {
+            let inner = &mut *inner;
+            inner.tick += 1;
+            let tick = inner.tick;
+            match inner.entries.get_mut(path) {
+                Some(entry) if entry.len == metadata.len() && entry.modified == modified => {
+                    inner.order.remove(&entry.used);
+                    inner.order.insert(tick, path.to_path_buf());
+                    entry.used = tick;
+                    return Ok(entry.contents.clone());
+                }
+                Some(_) => inner.remove(path),
+                None => {}
+            }
+        }
+
+        let contents = Bytes::from(fs::read(path)?);
+        // 读取期间文件可能被修改，长度不一致时不缓存
+        if contents.len() as u64 == metadata.len() {
+            self.insert(path, contents.clone(), modified);
+        }
+        Ok(contents)
+    }
+
+    fn insert(&self, path: &Path, contents: Bytes, modified: Option<SystemTime>) {
+        let mut inner = match self.inner.lock() {
+            Ok(inner) => inner,
+            Err(_) => return,
+        };
+        inner.remove(path);
+        // 淘汰最久没有使用的文件
+        while inner.size + contents.len() > self.capacity {
+            let oldest = match inner.order.first_key_value() {
+                Some((_, oldest)) => oldest.clone(),
+                None => break,
+            };
+            inner.remove(&oldest);
+        }
+        inner.tick += 1;
+        let tick = inner.tick;
+        inner.order.insert(tick, path.to_path_buf());
+        inner.size += contents.len();
+        inner.entries.insert(
+            path.to_path_buf(),
+            Entry {
+                len: contents.len() as u64,
+                contents,
+                modified,
+                used: tick,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::time::Duration;
+
+    // 每个测试使用单独的临时目录
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("file-cache-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn read(cache: &FileCache, path: &Path) -> Bytes {
+        cache.read(path, &fs::metadata(path).unwrap()).unwrap()
+    }
+
+    fn cached(cache: &FileCache) -> Vec<PathBuf> {
+        let inner = cache.inner.lock().unwrap();
+        inner.order.values().cloned().collect()
+    }
+
+    #[test]
+    fn evict_least_recently_used_by_bytes() {
+        let dir = temp_dir("evict");
+        let paths: Vec<PathBuf> = (0..9).map(|i| dir.join(format!("{}.txt", i))).collect();
+        for path in &paths {
+            fs::write(path, [b'x'; 12]).unwrap();
+        }
+        // 100字节只能放下8个12字节的文件
+        let cache = FileCache::new(100);
+        for path in &paths[..8] {
+            read(&cache, path);
+        }
+        // 使用过的文件移到最后，淘汰的是1.txt
+        read(&cache, &paths[0]);
+        read(&cache, &paths[8]);
+        let mut expected = paths[2..].to_vec();
+        expected.insert(6, paths[0].clone());
+        assert_eq!(cached(&cache), expected);
+        assert_eq!(cache.inner.lock().unwrap().size, 96);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn skip_large_files() {
+        let dir = temp_dir("large");
+        let path = dir.join("large.txt");
+        fs::write(&path, [b'x'; 20]).unwrap();
+        // 超过容量1/8的文件不缓存
+        let cache = FileCache::new(100);
+        assert_eq!(read(&cache, &path).len(), 20);
+        assert!(cached(&cache).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn invalidate_on_change() {
+        let dir = temp_dir("invalidate");
+        let path = dir.join("a.txt");
+        fs::write(&path, "old").unwrap();
+        let cache = FileCache::new(1024);
+        assert_eq!(read(&cache, &path), "old");
+
+        // 长度变化
+        fs::write(&path, "longer").unwrap();
+        assert_eq!(read(&cache, &path), "longer");
+
+        // 长度不变，修改时间变化
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        fs::write(&path, "newer!").unwrap();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified + Duration::from_secs(10))
+            .unwrap();
+        assert_eq!(read(&cache, &path), "newer!");
+
+        // metadata不变时使用缓存的内容
+        let metadata = fs::metadata(&path).unwrap();
+        fs::write(&path, "edited").unwrap();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(metadata.modified().unwrap())
+            .unwrap();
+        assert_eq!(read(&cache, &path), "newer!");
+        assert_eq!(cached(&cache), vec![path.clone()]);
+        assert_eq!(cache.inner.lock().unwrap().size, 6);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
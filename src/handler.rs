@@ -1,45 +1,730 @@
+use crate::caching::{CachePolicy, EtagMode, Validators};
+use crate::compression;
 use crate::constant;
-use crate::request::HttpRequest;
-use crate::response::{HttpResponse, HttpStatus};
+use crate::embed::{self, EMBED_DIR};
+use crate::file_cache::FileCache;
+use crate::listing::{self, SortKey};
+use crate::mime::MimeTypes;
+use crate::range::{self, RangeRequest};
+use crate::request::{HttpMethod, HttpRequest};
+use crate::response::{Body, HttpResponse, HttpStatus};
+use crate::utils::url_decode;
+use bytes::Bytes;
+use std::borrow::Cow;
 use std::collections::BTreeMap;
-use std::{env, fs};
+use std::fs::{self, File, Metadata};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
 
 // handler接口
-pub trait Handler {
-    fn handle<'a>(req: &HttpRequest) -> HttpResponse<'a>;
-    fn load_file(file_name: &str) -> Option<Vec<u8>> {
-        let file_path = format!("{}/static/{}", env!("CARGO_MANIFEST_DIR"), file_name);
-        fs::read(file_path).ok()
+pub trait Handler: Send + Sync {
+    fn handle(&self, req: &HttpRequest) -> HttpResponse<'static>;
+}
+
+// 普通函数和闭包也可以作为handler
+impl<F> Handler for F
+where
+    F: Fn(&HttpRequest) -> HttpResponse<'static> + Send + Sync,
+{
+    fn handle(&self, req: &HttpRequest) -> HttpResponse<'static> {
+        self(req)
+    }
+}
+
+// 符号链接策略
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SymlinkPolicy {
+    // 允许任意符号链接
+    Follow,
+    // 只允许指向资源目录内部的符号链接
+    WithinRoot,
+    // 不允许符号链接
+    Deny,
+}
+
+// 预压缩文件的编码和扩展名，靠前的优先
+const PRECOMPRESSED: [(&str, &str); 2] = [("br", ".br"), ("gzip", ".gz")];
+
+// 请求路径解析的结果
+enum Resolved {
+    File(PathBuf),
+    Dir {
+        // 所在的资源目录
+        root: PathBuf,
+        relative: PathBuf,
+        index: Option<PathBuf>,
+    },
+}
+
+// 响应内容，大文件不读入内存
+enum Contents {
+    Memory(Bytes),
+    File(File, u64),
+}
+
+impl Contents {
+    fn len(&self) -> u64 {
+        match self {
+            Contents::Memory(bytes) => bytes.len() as u64,
+            Contents::File(_, len) => *len,
+        }
+    }
+
+    // 用于嗅探类型的开头部分
+    fn head(&self) -> Cow<'_, [u8]> {
+        match self {
+            Contents::Memory(bytes) => Cow::Borrowed(bytes),
+            Contents::File(file, _) => {
+                let mut head = Vec::new();
+                let _ = file.take(512).read_to_end(&mut head);
+                Cow::Owned(head)
+            }
+        }
+    }
+
+    fn into_body(self) -> Body {
+        match self {
+            Contents::Memory(bytes) => Body::Full(bytes),
+            Contents::File(file, len) => Body::File {
+                file,
+                offset: 0,
+                len,
+            },
+        }
+    }
+
+    // 闭区间 [start, end] 的内容
+    fn slice(self, start: u64, end: u64) -> Body {
+        match self {
+            Contents::Memory(bytes) => Body::Full(bytes.slice(start as usize..=end as usize)),
+            Contents::File(file, _) => Body::File {
+                file,
+                offset: start,
+                len: end - start + 1,
+            },
+        }
     }
 }
 
 // 静态资源处理器
-pub struct StaticHandler;
+pub struct StaticHandler {
+    // 资源目录，按顺序查找，靠前的优先
+    roots: Vec<PathBuf>,
+    symlinks: SymlinkPolicy,
+    // 是否允许访问.开头的文件和目录
+    dotfiles: bool,
+    // 目录中没有index.html时是否显示目录列表
+    listing: bool,
+    // 是否使用 .br、.gz 预压缩文件
+    precompressed: bool,
+    mime_types: MimeTypes,
+    etag: EtagMode,
+    cache_policy: CachePolicy,
+    // 文件内容缓存，None时每次都从磁盘读取
+    file_cache: Option<FileCache>,
+    // 使用编译进可执行文件的资源
+    embedded: bool,
+    // 内嵌模式下改为读取磁盘上的资源目录
+    live: bool,
+    // 不小于该大小的文件直接从文件发送，不读入内存
+    sendfile_threshold: u64,
+}
 
-impl Handler for StaticHandler {
-    fn handle<'a>(req: &HttpRequest) -> HttpResponse<'a> {
-        let route: Vec<&str> = req.url().split("/").collect();
-        match route[1] {
-            // 访问"/"等于访问"/index.html"
-            "" => {
-                let mut headers = BTreeMap::new();
-                headers.insert("Content-Type", constant::TEXT_HTML);
-                HttpResponse::new(HttpStatus::Ok, Some(headers), Self::load_file("index.html"))
-            }
-            path => match Self::load_file(path) {
-                Some(contents) => {
-                    let mut headers = BTreeMap::new();
-                    if path.ends_with(".css") {
-                        headers.insert("Content-Type", constant::TEXT_CSS);
-                    } else if path.ends_with(".js") {
-                        headers.insert("Content-Type", constant::TEXT_JAVASCRIPT);
-                    } else {
-                        headers.insert("Content-Type", constant::TEXT_HTML);
+#[allow(dead_code)]
+impl StaticHandler {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self::with_roots([root])
+    }
+
+    // 多个资源目录
+    pub fn with_roots<I, P>(roots: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        Self {
+            roots: roots.into_iter().map(Into::into).collect(),
+            symlinks: SymlinkPolicy::WithinRoot,
+            dotfiles: false,
+            listing: false,
+            precompressed: true,
+            mime_types: MimeTypes::new(),
+            etag: EtagMode::Strong,
+            cache_policy: CachePolicy::new(),
+            file_cache: None,
+            embedded: false,
+            live: false,
+            sendfile_threshold: 1 << 20,
+        }
+    }
+
+    // 使用编译进可执行文件的资源，需要启用embed特性
+    pub fn embedded() -> Self {
+        let mut handler = Self::new(EMBED_DIR);
+        handler.embedded = true;
+        handler
+    }
+
+    // 内嵌模式下读取磁盘上的资源目录，开发时修改文件后不需要重新编译
+    // 默认是编译时的资源目录，可以用roots替换
+    pub fn live(mut self, live: bool) -> Self {
+        self.live = live;
+        self
+    }
+
+    // 替换全部资源目录
+    pub fn roots<I, P>(mut self, roots: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        self.roots = roots.into_iter().map(Into::into).collect();
+        self
+    }
+
+    // 追加一个优先级更低的资源目录
+    pub fn root<P: Into<PathBuf>>(mut self, root: P) -> Self {
+        self.roots.push(root.into());
+        self
+    }
+
+    pub fn symlinks(mut self, policy: SymlinkPolicy) -> Self {
+        self.symlinks = policy;
+        self
+    }
+
+    pub fn dotfiles(mut self, allow: bool) -> Self {
+        self.dotfiles = allow;
+        self
+    }
+
+    pub fn listing(mut self, listing: bool) -> Self {
+        self.listing = listing;
+        self
+    }
+
+    pub fn precompressed(mut self, precompressed: bool) -> Self {
+        self.precompressed = precompressed;
+        self
+    }
+
+    // 自定义扩展名的MIME类型
+    pub fn mime_type(mut self, extension: &str, mime: &str) -> Self {
+        self.mime_types.insert(extension, mime);
+        self
+    }
+
+    // 没有扩展名的文件根据内容判断类型
+    pub fn sniff(mut self, sniff: bool) -> Self {
+        self.mime_types.set_sniff(sniff);
+        self
+    }
+
+    pub fn etag(mut self, mode: EtagMode) -> Self {
+        self.etag = mode;
+        self
+    }
+
+    // 按路径设置Cache-Control，pattern支持*通配，如 *.css、/assets/*
+    pub fn cache_control(mut self, pattern: &str, cache_control: &str) -> Self {
+        self.cache_policy.insert(pattern, cache_control);
+        self
+    }
+
+    // 在内存中缓存文件内容，capacity为最大字节数
+    pub fn file_cache(mut self, capacity: usize) -> Self {
+        self.file_cache = (capacity > 0).then(|| FileCache::new(capacity));
+        self
+    }
+
+    pub fn sendfile_threshold(mut self, threshold: u64) -> Self {
+        self.sendfile_threshold = threshold;
+        self
+    }
+
+    // 把请求路径解析为资源目录中的文件或目录，无法安全访问时返回None
+    fn resolve(&self, url: &str) -> Option<Resolved> {
+        let parts = normalize_path(url)?;
+        if !self.dotfiles && parts.iter().any(|p| p.starts_with('.')) {
+            return None;
+        }
+        let relative: PathBuf = parts.iter().collect();
+        // 第一个存在该路径的资源目录优先
+        let root = self
+            .roots
+            .iter()
+            .find(|root| root.join(&relative).exists() && self.check_symlinks(root, &relative))?;
+        let path = root.join(&relative);
+        if path.is_file() {
+            return Some(Resolved::File(path));
+        }
+        // 目录中的index.html，可以来自任意资源目录
+        let index_relative = relative.join("index.html");
+        let index = self.roots.iter().find_map(|root| {
+            let index = root.join(&index_relative);
+            (index.is_file() && self.check_symlinks(root, &index_relative)).then_some(index)
+        });
+        Some(Resolved::Dir {
+            root: root.clone(),
+            relative,
+            index,
+        })
+    }
+
+    // 目录列表，支持 ?sort=name|size|mtime&order=asc|desc，?format=json 或 Accept为JSON时返回JSON
+    fn render_listing(
+        &self,
+        req: &HttpRequest,
+        root: &Path,
+        relative: &Path,
+    ) -> HttpResponse<'static> {
+        let entries = listing::read_entries(&root.join(relative), |name| {
+            (self.dotfiles || !name.starts_with('.'))
+                && self.check_symlinks(root, &relative.join(name))
+        });
+        let mut entries = match entries {
+            Ok(entries) => entries,
+            Err(_) => return HttpResponse::not_found(self.load_file("/404.html")),
+        };
+        let params = req.search_params();
+        let key = SortKey::parse(params.get("sort").map_or("name", |v| v.as_ref()));
+        let descending = params.get("order").is_some_and(|v| v == "desc");
+        listing::sort(&mut entries, key, descending);
+
+        let path = req.original_url();
+        let json = params.get("format").is_some_and(|v| v == "json")
+            || req
+                .headers()
+                .get("accept")
+                .is_some_and(|a| a.contains(constant::APPLICATION_JSON));
+        let (content_type, body) = if json {
+            (
+                constant::APPLICATION_JSON,
+                listing::render_json(path, &entries),
+            )
+        } else {
+            (
+                constant::TEXT_HTML_UTF8,
+                listing::render_html(path, &entries, key, descending),
+            )
+        };
+        let mut headers = BTreeMap::new();
+        headers.insert("Content-Type", content_type);
+        HttpResponse::new(HttpStatus::Ok, Some(headers), Some(body.into_bytes()))
+    }
+
+    // 按策略检查路径中的符号链接
+    fn check_symlinks(&self, root: &Path, relative: &Path) -> bool {
+        match self.symlinks {
+            SymlinkPolicy::Follow => true,
+            SymlinkPolicy::WithinRoot => {
+                match (root.canonicalize(), root.join(relative).canonicalize()) {
+                    (Ok(root), Ok(path)) => path.starts_with(root),
+                    _ => false,
+                }
+            }
+            SymlinkPolicy::Deny => {
+                let mut path = root.to_path_buf();
+                relative.components().all(|c| {
+                    path.push(c);
+                    fs::symlink_metadata(&path).is_ok_and(|m| !m.file_type().is_symlink())
+                })
+            }
+        }
+    }
+
+    // 查找 .br、.gz 预压缩文件，返回客户端可以接受的文件和编码，以及是否存在预压缩文件
+    fn find_precompressed(
+        &self,
+        req: &HttpRequest,
+        path: &Path,
+    ) -> (Option<(PathBuf, &'static str)>, bool) {
+        if !self.precompressed {
+            return (None, false);
+        }
+        let accept_encoding = req.headers().get("accept-encoding").copied();
+        let mut vary = false;
+        for (coding, extension) in PRECOMPRESSED {
+            let mut sibling = path.as_os_str().to_owned();
+            sibling.push(extension);
+            let sibling = PathBuf::from(sibling);
+            // 除非允许任意符号链接，预压缩文件必须是普通文件
+            let allowed = match self.symlinks {
+                SymlinkPolicy::Follow => sibling.is_file(),
+                _ => fs::symlink_metadata(&sibling).is_ok_and(|m| m.file_type().is_file()),
+            };
+            if !allowed {
+                continue;
+            }
+            vary = true;
+            if compression::accepts(accept_encoding, coding) {
+                return (Some((sibling, coding)), true);
+            }
+        }
+        (None, vary)
+    }
+
+    fn set_cache_headers(
+        &self,
+        response: &mut HttpResponse,
+        validators: &Validators,
+        cache_control: Option<&str>,
+        vary: bool,
+    ) {
+        if vary {
+            response.set_header("Vary", "Accept-Encoding");
+        }
+        if let Some(etag) = &validators.etag {
+            response.set_header("ETag", etag.clone());
+        }
+        if let Some(last_modified) = validators.last_modified_header() {
+            response.set_header("Last-Modified", last_modified);
+        }
+        if let Some(cache_control) = cache_control {
+            response.set_header("Cache-Control", cache_control.to_string());
+        }
+    }
+
+    // 从内嵌的资源中查找
+    fn handle_embedded(&self, req: &HttpRequest) -> HttpResponse<'static> {
+        let not_found = || HttpResponse::not_found(self.load_file("/404.html"));
+        let parts = match normalize_path(req.url()) {
+            Some(parts) if self.dotfiles || !parts.iter().any(|p| p.starts_with('.')) => parts,
+            _ => return not_found(),
+        };
+        let path = format!("/{}", parts.join("/"));
+        let asset = match embed::find(&path) {
+            Some(asset) => asset,
+            None if embed::is_dir(&path) => {
+                if let Some(response) = redirect_dir(req) {
+                    return response;
+                }
+                match embed::find(&format!("{}/index.html", path.trim_end_matches('/'))) {
+                    Some(asset) => asset,
+                    None => return not_found(),
+                }
+            }
+            None => return not_found(),
+        };
+
+        let mut encoding = None;
+        let mut vary = false;
+        if self.precompressed {
+            let accept_encoding = req.headers().get("accept-encoding").copied();
+            for (coding, _) in PRECOMPRESSED {
+                if asset.encoded(coding).is_some() {
+                    vary = true;
+                    if compression::accepts(accept_encoding, coding) {
+                        encoding = Some(coding);
+                        break;
                     }
-                    HttpResponse::new(HttpStatus::Ok, Some(headers), Some(contents))
                 }
-                None => HttpResponse::not_found(Self::load_file("404.html")),
-            },
+            }
+        }
+        // 压缩后的内容使用不同的ETag
+        let tag = match encoding {
+            Some(coding) => format!("{}-{}\"", asset.etag.trim_end_matches('"'), coding),
+            None => asset.etag.to_string(),
+        };
+        let modified = UNIX_EPOCH + Duration::from_secs(asset.modified);
+        let validators = Validators::from_tag(tag, Some(modified), self.etag);
+        let cache_control = self.cache_policy.lookup(&path);
+
+        if let Some(status) = validators.evaluate(req) {
+            let mut response = empty_response(status, None);
+            self.set_cache_headers(&mut response, &validators, cache_control, vary);
+            return response;
         }
+        let contents = encoding
+            .and_then(|coding| asset.encoded(coding))
+            .unwrap_or(asset.contents);
+        self.respond(
+            req,
+            asset.mime.to_string(),
+            Contents::Memory(Bytes::from_static(contents)),
+            encoding,
+            &validators,
+            cache_control,
+            vary,
+        )
+    }
+
+    // 根据Range生成完整或部分内容的响应
+    #[allow(clippy::too_many_arguments)]
+    fn respond(
+        &self,
+        req: &HttpRequest,
+        content_type: String,
+        contents: Contents,
+        encoding: Option<&'static str>,
+        validators: &Validators,
+        cache_control: Option<&str>,
+        vary: bool,
+    ) -> HttpResponse<'static> {
+        let mut headers = BTreeMap::new();
+        headers.insert("X-Content-Type-Options", "nosniff");
+        headers.insert("Accept-Ranges", "bytes");
+        if let Some(coding) = encoding {
+            headers.insert("Content-Encoding", coding);
+        }
+        let total = contents.len();
+        let mut response = match range_request(req, validators, total) {
+            RangeRequest::Full => {
+                let mut response = HttpResponse::new(HttpStatus::Ok, Some(headers), None);
+                response.set_body(Some(contents.into_body()));
+                response.set_header("Content-Type", content_type);
+                response
+            }
+            RangeRequest::Partial(ranges) if ranges.len() == 1 => {
+                let range = ranges[0];
+                let mut response =
+                    HttpResponse::new(HttpStatus::PartialContent, Some(headers), None);
+                response.set_body(Some(contents.slice(range.start, range.end)));
+                response.set_header("Content-Type", content_type);
+                response.set_header("Content-Range", range.content_range(total));
+                response
+            }
+            RangeRequest::Partial(ranges) => {
+                let boundary = range::boundary();
+                let body = match contents {
+                    Contents::Memory(bytes) => Body::Full(
+                        range::multipart_body(&bytes, &ranges, &content_type, &boundary).into(),
+                    ),
+                    // 大文件按范围读取后发送，文件无法定位时返回500
+                    Contents::File(mut file, _) => {
+                        if file.seek(SeekFrom::Start(ranges[0].start)).is_err() {
+                            return HttpResponse::new(
+                                HttpStatus::InternalServerError,
+                                None::<BTreeMap<&str, &str>>,
+                                None,
+                            );
+                        }
+                        Body::Stream(range::multipart_stream(
+                            file,
+                            total,
+                            ranges,
+                            content_type,
+                            boundary.clone(),
+                        ))
+                    }
+                };
+                let mut response =
+                    HttpResponse::new(HttpStatus::PartialContent, Some(headers), None);
+                response.set_body(Some(body));
+                response.set_header(
+                    "Content-Type",
+                    format!("multipart/byteranges; boundary={}", boundary),
+                );
+                response
+            }
+            RangeRequest::Unsatisfiable => {
+                let mut response = empty_response(HttpStatus::RangeNotSatisfiable, Some(headers));
+                response.set_header("Content-Range", format!("bytes */{}", total));
+                response
+            }
+        };
+        self.set_cache_headers(&mut response, validators, cache_control, vary);
+        response
+    }
+
+    fn read_file(&self, path: &Path, metadata: &Metadata) -> io::Result<Bytes> {
+        match &self.file_cache {
+            Some(cache) => cache.read(path, metadata),
+            None => fs::read(path).map(Bytes::from),
+        }
+    }
+
+    fn load_file(&self, url: &str) -> Option<Vec<u8>> {
+        if self.embedded && !self.live {
+            return embed::find(url).map(|asset| asset.contents.to_vec());
+        }
+        match self.resolve(url)? {
+            Resolved::File(path) => {
+                let metadata = fs::metadata(&path).ok()?;
+                self.read_file(&path, &metadata)
+                    .ok()
+                    .map(|bytes| bytes.to_vec())
+            }
+            Resolved::Dir { .. } => None,
+        }
+    }
+}
+
+// 只有GET请求处理Range，If-Range不匹配时返回完整内容
+fn range_request(req: &HttpRequest, validators: &Validators, total: u64) -> RangeRequest {
+    if !matches!(req.method(), HttpMethod::Get | HttpMethod::Head) {
+        return RangeRequest::Full;
+    }
+    let headers = req.headers();
+    match headers.get("range") {
+        Some(range) => match headers.get("if-range") {
+            Some(if_range) if !range::if_range_matches(if_range, validators) => RangeRequest::Full,
+            _ => RangeRequest::parse(range, total),
+        },
+        None => RangeRequest::Full,
+    }
+}
+
+// 没有响应体的304、412和416，不带默认的Content-Type
+// 缓存会把304的响应头合并到已保存的响应中，错误的类型会覆盖原来的类型
+fn empty_response(
+    status: HttpStatus,
+    headers: Option<BTreeMap<&'static str, &'static str>>,
+) -> HttpResponse<'static> {
+    let mut response = HttpResponse::new(status, headers, None);
+    response.remove_header("Content-Type");
+    response
+}
+
+// 解码并规范化请求路径，去掉"."和".."，越过根目录时返回None
+fn normalize_path(url: &str) -> Option<Vec<String>> {
+    let decoded = url_decode(url, false);
+    let mut parts = Vec::new();
+    for part in decoded.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            // 拒绝Windows路径分隔符和空字符
+            part if part.contains(['\\', '\0']) => return None,
+            part => parts.push(part.to_string()),
+        }
+    }
+    Some(parts)
+}
+
+// 目录地址需要以/结尾，否则页面中的相对地址会出错
+fn redirect_dir(req: &HttpRequest) -> Option<HttpResponse<'static>> {
+    if req.url().ends_with('/') {
+        return None;
+    }
+    // 开头的多个/会被浏览器当作协议相对地址，重定向到其他主机
+    let path = req.original_url().trim_start_matches(['/', '\\']);
+    let mut location = format!("/{}/", path);
+    if !req.query().is_empty() {
+        location = format!("{}?{}", location, req.query());
+    }
+    Some(HttpResponse::redirect(
+        HttpStatus::MovedPermanently,
+        location,
+    ))
+}
+
+impl Handler for StaticHandler {
+    fn handle(&self, req: &HttpRequest) -> HttpResponse<'static> {
+        if self.embedded && !self.live {
+            return self.handle_embedded(req);
+        }
+        let path = match self.resolve(req.url()) {
+            Some(Resolved::File(path)) => path,
+            Some(Resolved::Dir {
+                root,
+                relative,
+                index,
+            }) => {
+                if let Some(response) = redirect_dir(req) {
+                    return response;
+                }
+                match index {
+                    Some(index) => index,
+                    None if self.listing => return self.render_listing(req, &root, &relative),
+                    None => return HttpResponse::not_found(self.load_file("/404.html")),
+                }
+            }
+            None => return HttpResponse::not_found(self.load_file("/404.html")),
+        };
+        // 客户端支持时使用预压缩的文件
+        let (encoding, vary) = self.find_precompressed(req, &path);
+        let file_path = encoding.as_ref().map_or(&path, |(sibling, _)| sibling);
+        let metadata = match fs::metadata(file_path) {
+            Ok(metadata) => metadata,
+            Err(_) => return HttpResponse::not_found(self.load_file("/404.html")),
+        };
+        let validators = Validators::from_metadata(&metadata, self.etag);
+        let cache_control = normalize_path(req.url())
+            .and_then(|parts| self.cache_policy.lookup(&format!("/{}", parts.join("/"))));
+
+        // 条件请求
+        if let Some(status) = validators.evaluate(req) {
+            let mut response = empty_response(status, None);
+            self.set_cache_headers(&mut response, &validators, cache_control, vary);
+            return response;
+        }
+
+        let contents = if metadata.len() >= self.sendfile_threshold {
+            File::open(file_path).map(|file| Contents::File(file, metadata.len()))
+        } else {
+            self.read_file(file_path, &metadata).map(Contents::Memory)
+        };
+        let contents = match contents {
+            Ok(contents) => contents,
+            Err(_) => return HttpResponse::not_found(self.load_file("/404.html")),
+        };
+        // 压缩后的内容不能用于嗅探类型
+        let content_type = match encoding {
+            Some(_) => self.mime_types.guess(&path, &[]),
+            None => self.mime_types.guess(&path, &contents.head()),
+        };
+        self.respond(
+            req,
+            content_type,
+            contents,
+            encoding.map(|(_, coding)| coding),
+            &validators,
+            cache_control,
+            vary,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::normalize_path;
+
+    #[test]
+    fn normalize_plain_path() {
+        assert_eq!(normalize_path("/"), Some(vec![]));
+        assert_eq!(
+            normalize_path("/css//./site.css"),
+            Some(vec!["css".to_string(), "site.css".to_string()])
+        );
+    }
+
+    #[test]
+    fn normalize_dot_dot() {
+        // 不超出根目录的..可以正常回退
+        assert_eq!(
+            normalize_path("/a/b/../c"),
+            Some(vec!["a".to_string(), "c".to_string()])
+        );
+        assert_eq!(normalize_path("/.."), None);
+        assert_eq!(normalize_path("/a/../../etc/passwd"), None);
+    }
+
+    #[test]
+    fn normalize_encoded_dot_dot() {
+        assert_eq!(normalize_path("/%2e%2e/etc/passwd"), None);
+        assert_eq!(normalize_path("/a/%2E%2e/%2e%2E/etc"), None);
+        assert_eq!(normalize_path("/a/%2e%2e/b"), Some(vec!["b".to_string()]));
+    }
+
+    #[test]
+    fn normalize_encoded_slash() {
+        // 解码后的/按路径分隔符处理，不能借此绕过..的检查
+        assert_eq!(
+            normalize_path("/a%2fb"),
+            Some(vec!["a".to_string(), "b".to_string()])
+        );
+        assert_eq!(normalize_path("/a%2f..%2f..%2fetc"), None);
+        assert_eq!(normalize_path("/%2F%2E%2E%2Fetc"), None);
+    }
+
+    #[test]
+    fn normalize_rejects_nul_and_backslash() {
+        assert_eq!(normalize_path("/index.html%00.txt"), None);
+        assert_eq!(normalize_path("/a%5c..%5c..%5cwindows"), None);
+        assert_eq!(normalize_path("/a\\b"), None);
     }
 }
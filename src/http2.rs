@@ -0,0 +1,286 @@
+use crate::error::{Fail, Result};
+use crate::listener::PeerAddr;
+use crate::request::{Extensions, HttpRequest};
+use crate::response::{Body, HttpResponse, HttpStatus};
+use crate::server::HttpSettings;
+use crate::vhost::VirtualHosts;
+use bytes::Bytes;
+use h2::server::SendResponse;
+use h2::{Reason, RecvStream, SendStream};
+use http::request::Parts;
+use http::{Method, Request, Response};
+use std::collections::BTreeMap;
+use std::future::poll_fn;
+use std::io;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite};
+
+// 明文HTTP/2连接的开头
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+// 单个连接上同时处理的最大流数量
+const MAX_CONCURRENT_STREAMS: u32 = 100;
+
+// HTTP/2中不允许出现的连接相关响应头
+const CONNECTION_HEADERS: [&str; 5] = [
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+// 检查明文连接是否以HTTP/2的连接前言开头(prior knowledge h2c)，
+// 返回已经读出的数据，需要交给之后处理连接的一方。不匹配时马上停止读取，短的HTTP/1请求不用等满24字节
+pub async fn read_preface<S: AsyncRead + Unpin>(stream: &mut S) -> io::Result<(bool, Vec<u8>)> {
+    let mut buffer = Vec::with_capacity(PREFACE.len());
+    let mut buf = [0u8; PREFACE.len()];
+    while buffer.len() < PREFACE.len() {
+        let length = stream
+            .read(&mut buf[..PREFACE.len() - buffer.len()])
+            .await?;
+        if length == 0 {
+            return Ok((false, buffer));
+        }
+        buffer.extend_from_slice(&buf[..length]);
+        if !PREFACE.starts_with(&buffer) {
+            return Ok((false, buffer));
+        }
+    }
+    Ok((true, buffer))
+}
+
+// 处理一个HTTP/2连接，每个流在单独的任务中交给路由处理
+pub async fn serve<S>(
+    io: S,
+    http_settings: Arc<HttpSettings>,
+    hosts: Arc<VirtualHosts>,
+    addr: PeerAddr,
+    extensions: Extensions,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut connection = h2::server::Builder::new()
+        .max_concurrent_streams(MAX_CONCURRENT_STREAMS)
+        .max_header_list_size(http_settings.max_header_size as u32)
+        .handshake::<_, Bytes>(io)
+        .await?;
+    let extensions = Arc::new(extensions);
+    while let Some(stream) = connection.accept().await {
+        let (request, respond) = stream?;
+        let http_settings = http_settings.clone();
+        let hosts = hosts.clone();
+        let extensions = extensions.clone();
+        tokio::spawn(async move {
+            if let Err(err) =
+                handle_stream(&http_settings, &hosts, request, respond, addr, &extensions).await
+            {
+                println!("{}", err);
+            }
+        });
+    }
+    Ok(())
+}
+
+async fn handle_stream(
+    http_settings: &HttpSettings,
+    hosts: &VirtualHosts,
+    request: Request<RecvStream>,
+    respond: SendResponse<Bytes>,
+    addr: PeerAddr,
+    extensions: &Extensions,
+) -> Result<()> {
+    let (parts, mut recv) = request.into_parts();
+    let head_only = parts.method == Method::HEAD;
+    // 读取请求体，读取后释放流量控制窗口
+    let mut body = Vec::new();
+    while let Some(chunk) = recv.data().await {
+        let chunk = chunk?;
+        recv.flow_control().release_capacity(chunk.len())?;
+        if body.len() + chunk.len() > http_settings.max_body_size {
+            let response = HttpResponse::new(
+                HttpStatus::BadRequest,
+                None::<BTreeMap<&str, &str>>,
+                Some("请求体大小超出限制".as_bytes().to_vec()),
+            );
+            return send_response(respond, response, head_only).await;
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    // 转换为HTTP/1格式的请求头，使用相同的解析和路由
+    let header = raw_header(&parts);
+    let ip = addr.request_ip();
+    let response = match HttpRequest::from(&header, body, &ip) {
+        Ok(mut request) => {
+            for extension in extensions {
+                request.insert_extension(extension.clone());
+            }
+            hosts.handle(request)
+        }
+        Err(err) => HttpResponse::new(
+            HttpStatus::BadRequest,
+            None::<BTreeMap<&str, &str>>,
+            Some(err.to_string().into_bytes()),
+        ),
+    };
+    send_response(respond, response, head_only).await
+}
+
+// 请求行和请求头，:authority作为Host，多个同名请求头合并
+fn raw_header(parts: &Parts) -> String {
+    let path = parts.uri.path_and_query().map_or("/", |path| path.as_str());
+    let mut headers: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (name, value) in &parts.headers {
+        if let Ok(value) = value.to_str() {
+            headers.entry(name.as_str()).or_default().push(value);
+        }
+    }
+    if let Some(authority) = parts.uri.authority() {
+        headers
+            .entry("host")
+            .or_insert_with(|| vec![authority.as_str()]);
+    }
+    let mut header = format!("{} {} HTTP/2.0\r\n", parts.method, path);
+    for (name, values) in headers {
+        let separator = if name == "cookie" { "; " } else { ", " };
+        header.push_str(&format!("{}: {}\r\n", name, values.join(separator)));
+    }
+    header.push_str("\r\n");
+    header
+}
+
+async fn send_response(
+    mut respond: SendResponse<Bytes>,
+    mut response: HttpResponse<'static>,
+    head_only: bool,
+) -> Result<()> {
+    let body = response.take_body();
+    let mut builder = Response::builder().status(response.status().code());
+    for (name, value) in response.header_pairs() {
+        if !CONNECTION_HEADERS.contains(&name.to_lowercase().as_str()) {
+            builder = builder.header(name, value);
+        }
+    }
+    let length = match &body {
+        Some(Body::Full(bytes)) => Some(bytes.len() as u64),
+        Some(Body::File { len, .. }) => Some(*len),
+        Some(Body::Stream(_)) => None,
+        None => Some(0),
+    };
+    if let (Some(length), false) = (length, *response.status() == HttpStatus::NotModified) {
+        builder = builder.header("content-length", length);
+    }
+    let body = match body {
+        Some(body) if !head_only && *response.status() != HttpStatus::NotModified => body,
+        _ => {
+            respond.send_response(builder.body(())?, true)?;
+            return Ok(());
+        }
+    };
+    let mut stream = respond.send_response(builder.body(())?, false)?;
+    match body {
+        Body::Full(bytes) => send_data(&mut stream, bytes, true).await,
+        // 流式响应体和文件分块发送，发送失败时丢弃receiver使发送端停止
+        body => {
+            let mut receiver = body.into_stream();
+            while let Some(chunk) = receiver.recv().await {
+                match chunk {
+                    Ok(chunk) => send_data(&mut stream, Bytes::from(chunk), false).await?,
+                    // 读取失败时重置流，客户端不会把不完整的内容当作完整的响应
+                    Err(err) => {
+                        stream.send_reset(Reason::INTERNAL_ERROR);
+                        return Err(err.into());
+                    }
+                }
+            }
+            send_data(&mut stream, Bytes::new(), true).await
+        }
+    }
+}
+
+// 按流量控制窗口分块发送
+async fn send_data(stream: &mut SendStream<Bytes>, mut data: Bytes, end: bool) -> Result<()> {
+    loop {
+        if data.is_empty() {
+            // 没有剩余数据时单独发送结束标志
+            if end {
+                stream.send_data(Bytes::new(), true)?;
+            }
+            return Ok(());
+        }
+        stream.reserve_capacity(data.len());
+        let capacity = match poll_fn(|cx| stream.poll_capacity(cx)).await {
+            Some(capacity) => capacity?,
+            None => return Fail::from("HTTP/2流已关闭"),
+        };
+        if capacity == 0 {
+            continue;
+        }
+        let chunk = data.split_to(capacity.min(data.len()));
+        let last = end && data.is_empty();
+        stream.send_data(chunk, last)?;
+        if last {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::Rewind;
+    use tokio::io::{duplex, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn preface_split_across_reads() {
+        let (mut client, mut server) = duplex(64);
+        let reader = tokio::spawn(async move {
+            let read = read_preface(&mut server).await.unwrap();
+            (read, server)
+        });
+        client.write_all(&PREFACE[..10]).await.unwrap();
+        tokio::task::yield_now().await;
+        client.write_all(&PREFACE[10..]).await.unwrap();
+        client.write_all(b"frames").await.unwrap();
+        let ((h2, buffer), server) = reader.await.unwrap();
+        assert!(h2);
+        assert_eq!(buffer, PREFACE);
+        // 前言之后的数据还在流中
+        let mut rest = [0u8; 6];
+        Rewind::new(Vec::new(), server)
+            .read_exact(&mut rest)
+            .await
+            .unwrap();
+        assert_eq!(&rest, b"frames");
+    }
+
+    #[tokio::test]
+    async fn short_http1_request_is_replayed() {
+        let (mut client, mut server) = duplex(64);
+        client.write_all(b"GET / HTTP/1.0\r\n\r\n").await.unwrap();
+        // 比前言短的HTTP/1请求，不匹配时马上返回，不会等满24字节
+        let (h2, buffer) = read_preface(&mut server).await.unwrap();
+        assert!(!h2);
+        assert!(!buffer.is_empty());
+        assert!(b"GET / HTTP/1.0\r\n\r\n".starts_with(&buffer));
+        drop(client);
+        let mut request = Vec::new();
+        Rewind::new(buffer, server)
+            .read_to_end(&mut request)
+            .await
+            .unwrap();
+        assert_eq!(request, b"GET / HTTP/1.0\r\n\r\n");
+    }
+
+    #[tokio::test]
+    async fn partial_preface_then_close() {
+        let (mut client, mut server) = duplex(64);
+        client.write_all(b"PRI * HTTP").await.unwrap();
+        drop(client);
+        let (h2, buffer) = read_preface(&mut server).await.unwrap();
+        assert!(!h2);
+        assert_eq!(buffer, b"PRI * HTTP");
+    }
+}
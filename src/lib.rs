@@ -0,0 +1,56 @@
+// 服务器模块
+pub mod server;
+// 请求模块
+pub mod request;
+// 响应模块
+pub mod response;
+// 路由模块
+pub mod router;
+// 虚拟主机模块
+pub mod vhost;
+// 重写规则模块
+pub mod rewrite;
+// 处理器模块
+pub mod handler;
+// HTTP缓存模块
+pub mod caching;
+// 范围请求模块
+pub mod range;
+// 压缩模块
+pub mod compression;
+// 文件缓存模块
+pub mod file_cache;
+// 内嵌资源模块
+pub mod embed;
+// 目录列表模块
+pub mod listing;
+// MIME类型模块
+pub mod mime;
+// TLS模块
+pub mod tls;
+// HTTP/2模块
+pub mod http2;
+// HTTPS重定向模块
+pub mod redirect;
+// 连接模块
+pub mod connection;
+// PROXY协议模块
+pub mod proxy;
+// 监听模块
+pub mod listener;
+// 中间件模块
+pub mod middleware;
+// WebSocket模块
+pub mod websocket;
+// 服务器发送事件模块
+pub mod sse;
+// 参数提取模块
+pub mod extract;
+// 错误处理模块
+pub mod error;
+// 工具模块
+pub mod utils;
+// 常量
+pub mod constant;
+// 启动配置
+pub mod config;
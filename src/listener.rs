@@ -0,0 +1,578 @@
+use crate::connection::Connection;
+use crate::error::{Fail, Result};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::io::{AsRawFd, BorrowedFd, FromRawFd, RawFd};
+#[cfg(unix)]
+use std::path::PathBuf;
+#[cfg(unix)]
+use std::sync::{Mutex, OnceLock};
+#[cfg(unix)]
+use tokio::net::unix::UCred;
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+
+// systemd传递的第一个fd
+#[cfg(unix)]
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+// 热重启时传给新进程的监听fd，逗号分隔
+#[cfg(unix)]
+const INHERIT_ENV: &str = "MY_HTTP_SERVER_FDS";
+
+// 热重启后检查新进程是否启动成功的等待时间
+#[cfg(unix)]
+const SUCCESSOR_CHECK: std::time::Duration = std::time::Duration::from_secs(2);
+
+// 监听地址
+#[derive(Clone, Debug, PartialEq)]
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(PathBuf),
+    // 已经打开的监听套接字
+    #[cfg(unix)]
+    Fd(RawFd),
+}
+
+impl ListenAddr {
+    // 解析监听地址，unix:开头的是Unix套接字路径，主机名解析为全部地址
+    // fd:开头的是继承的fd，systemd表示systemd通过LISTEN_FDS传递的全部套接字
+    pub fn parse(addr: &str) -> Result<Vec<ListenAddr>> {
+        if let Some(path) = addr.strip_prefix("unix:") {
+            #[cfg(unix)]
+            return match path {
+                "" => Fail::from(format!("无效的监听地址: {}", addr)),
+                path => Ok(vec![ListenAddr::Unix(PathBuf::from(path))]),
+            };
+            #[cfg(not(unix))]
+            return Fail::from(format!("当前平台不支持Unix套接字: {}", path));
+        }
+        if let Some(fd) = addr.strip_prefix("fd:") {
+            #[cfg(unix)]
+            return match fd.parse::<RawFd>() {
+                Ok(fd) if fd >= 0 => Ok(vec![ListenAddr::Fd(fd)]),
+                _ => Fail::from(format!("无效的监听地址: {}", addr)),
+            };
+            #[cfg(not(unix))]
+            return Fail::from(format!("当前平台不支持继承fd: {}", fd));
+        }
+        if addr == "systemd" {
+            #[cfg(unix)]
+            return systemd_fds();
+            #[cfg(not(unix))]
+            return Fail::from("当前平台不支持systemd套接字激活");
+        }
+        if let Ok(socket_addr) = addr.parse::<SocketAddr>() {
+            return Ok(vec![ListenAddr::Tcp(socket_addr)]);
+        }
+        let socket_addrs: Vec<SocketAddr> = match addr.to_socket_addrs() {
+            Ok(socket_addrs) => socket_addrs.collect(),
+            Err(err) => return Fail::from(format!("无效的监听地址: {}: {}", addr, err)),
+        };
+        // 解析结果可能有重复的地址
+        let mut addrs = Vec::new();
+        for socket_addr in socket_addrs {
+            if !addrs.contains(&ListenAddr::Tcp(socket_addr)) {
+                addrs.push(ListenAddr::Tcp(socket_addr));
+            }
+        }
+        if addrs.is_empty() {
+            return Fail::from(format!("监听地址没有解析到IP: {}", addr));
+        }
+        Ok(addrs)
+    }
+
+    // TCP监听的端口，继承的fd通过套接字的本地地址判断，无法判断时为None
+    pub fn port(&self) -> Option<u16> {
+        match self {
+            ListenAddr::Tcp(addr) => Some(addr.port()),
+            #[cfg(unix)]
+            ListenAddr::Unix(_) => None,
+            #[cfg(unix)]
+            ListenAddr::Fd(fd) => local_addr(*fd)?.as_socket().map(|addr| addr.port()),
+        }
+    }
+}
+
+impl Display for ListenAddr {
+    fn fmt(&self, formatter: &mut Formatter) -> FmtResult {
+        match self {
+            ListenAddr::Tcp(addr) => write!(formatter, "{}", addr),
+            #[cfg(unix)]
+            ListenAddr::Unix(path) => write!(formatter, "unix:{}", path.display()),
+            #[cfg(unix)]
+            ListenAddr::Fd(fd) => write!(formatter, "fd:{}", fd),
+        }
+    }
+}
+
+// 套接字的本地地址，只在调用期间借用fd
+#[cfg(unix)]
+fn local_addr(fd: RawFd) -> Option<socket2::SockAddr> {
+    // SAFETY: 借用期间fd不会被关闭
+    let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+    socket2::SockRef::from(&fd).local_addr().ok()
+}
+
+// systemd套接字激活，LISTEN_PID不是当前进程时说明环境变量是给其他进程的
+// 热重启的新进程中systemd的fd编号不变，由旧进程一起传递
+#[cfg(unix)]
+fn systemd_fds() -> Result<Vec<ListenAddr>> {
+    let count = std::env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|count| count.parse::<RawFd>().ok())
+        .unwrap_or(0);
+    let pid = std::env::var("LISTEN_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok());
+    let fds = SD_LISTEN_FDS_START..SD_LISTEN_FDS_START + count.max(0);
+    let handed_over = match inherited().lock() {
+        Ok(inherited) => fds.clone().all(|fd| inherited.contains(&fd)),
+        Err(_) => false,
+    };
+    if fds.is_empty() || (pid != Some(std::process::id()) && !handed_over) {
+        return Fail::from("没有systemd传递的套接字");
+    }
+    Ok(fds.map(ListenAddr::Fd).collect())
+}
+
+// 热重启时从旧进程继承、还没有使用的监听fd
+#[cfg(unix)]
+fn inherited() -> &'static Mutex<Vec<RawFd>> {
+    static INHERITED: OnceLock<Mutex<Vec<RawFd>>> = OnceLock::new();
+    INHERITED.get_or_init(|| {
+        let fds = std::env::var(INHERIT_ENV).unwrap_or_default();
+        Mutex::new(fds.split(',').filter_map(|fd| fd.parse().ok()).collect())
+    })
+}
+
+// 取出与addr相同的继承fd，地址按套接字的本地地址比较
+#[cfg(unix)]
+fn take_inherited(addr: &ListenAddr) -> Option<RawFd> {
+    let mut inherited = inherited().lock().ok()?;
+    let index = inherited.iter().position(|&fd| match addr {
+        ListenAddr::Fd(target) => fd == *target,
+        ListenAddr::Tcp(addr) => local_addr(fd).and_then(|a| a.as_socket()) == Some(*addr),
+        ListenAddr::Unix(path) => {
+            local_addr(fd).is_some_and(|local| local.as_pathname() == Some(path.as_path()))
+        }
+    })?;
+    Some(inherited.remove(index))
+}
+
+// 关闭没有用到的继承fd，如新进程的配置中去掉了某个地址
+pub fn close_inherited() {
+    #[cfg(unix)]
+    if let Ok(mut inherited) = inherited().lock() {
+        for fd in inherited.drain(..) {
+            // SAFETY: 继承的fd只在这里关闭，没有其他所有者
+            drop(unsafe { Socket::from_raw_fd(fd) });
+        }
+    }
+}
+
+// 监听套接字实际的本地地址，绑定后确定一次，作为请求的扩展传给handler
+// 继承的fd按套接字的实际类型区分，不会在处理请求时再查询
+#[derive(Clone, Debug, PartialEq)]
+pub enum LocalAddr {
+    Tcp(SocketAddr),
+    // 套接字文件路径，匿名套接字没有路径
+    #[cfg(unix)]
+    Unix(Option<PathBuf>),
+}
+
+impl LocalAddr {
+    pub fn is_unix(&self) -> bool {
+        match self {
+            LocalAddr::Tcp(_) => false,
+            #[cfg(unix)]
+            LocalAddr::Unix(_) => true,
+        }
+    }
+
+    pub fn port(&self) -> Option<u16> {
+        match self {
+            LocalAddr::Tcp(addr) => Some(addr.port()),
+            #[cfg(unix)]
+            LocalAddr::Unix(_) => None,
+        }
+    }
+}
+
+// 客户端地址，Unix套接字的连接没有IP，只有对端进程的凭证
+#[derive(Clone, Copy, Debug)]
+pub enum PeerAddr {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(Option<UCred>),
+}
+
+impl PeerAddr {
+    pub fn ip(&self) -> Option<IpAddr> {
+        self.socket_addr().map(|addr| addr.ip())
+    }
+
+    pub fn socket_addr(&self) -> Option<SocketAddr> {
+        match self {
+            PeerAddr::Tcp(addr) => Some(*addr),
+            #[cfg(unix)]
+            PeerAddr::Unix(_) => None,
+        }
+    }
+
+    pub fn is_unix(&self) -> bool {
+        self.socket_addr().is_none()
+    }
+
+    // HttpRequest::ip()的值，Unix套接字的连接为"unix"，不会与回环地址混淆
+    pub fn request_ip(&self) -> String {
+        match self.ip() {
+            Some(ip) => ip.to_string(),
+            None => String::from("unix"),
+        }
+    }
+}
+
+impl Display for PeerAddr {
+    fn fmt(&self, formatter: &mut Formatter) -> FmtResult {
+        match self {
+            PeerAddr::Tcp(addr) => write!(formatter, "{}", addr),
+            #[cfg(unix)]
+            PeerAddr::Unix(Some(cred)) => match cred.pid() {
+                Some(pid) => write!(formatter, "unix:uid={},pid={}", cred.uid(), pid),
+                None => write!(formatter, "unix:uid={}", cred.uid()),
+            },
+            #[cfg(unix)]
+            PeerAddr::Unix(None) => write!(formatter, "unix"),
+        }
+    }
+}
+
+// 已绑定的监听套接字
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+impl Listener {
+    // 绑定地址，others是同时监听的其他地址，用于决定IPv6是否同时接受IPv4
+    pub fn bind(addr: &ListenAddr, others: &[ListenAddr], unix_mode: Option<u32>) -> Result<Self> {
+        match open(addr, others, unix_mode) {
+            Ok(listener) => Ok(listener),
+            Err(err) => Fail::from(format!("监听 {} 失败: {}", addr, err)),
+        }
+    }
+
+    // 本地地址，类型由监听套接字本身决定，TCP套接字取不到地址时返回错误
+    pub fn local_addr(&self) -> io::Result<LocalAddr> {
+        match self {
+            Listener::Tcp(listener) => listener.local_addr().map(LocalAddr::Tcp),
+            #[cfg(unix)]
+            Listener::Unix(listener) => {
+                let addr = listener.local_addr().ok();
+                let path = addr.as_ref().and_then(|addr| addr.as_pathname());
+                Ok(LocalAddr::Unix(path.map(|path| path.to_path_buf())))
+            }
+        }
+    }
+
+    // 用于显示的地址
+    pub fn url(&self, secure: bool) -> String {
+        match self.local_addr() {
+            Ok(LocalAddr::Tcp(addr)) => {
+                let scheme = if secure { "https" } else { "http" };
+                format!("{}://{}", scheme, addr)
+            }
+            #[cfg(unix)]
+            Ok(LocalAddr::Unix(Some(path))) => format!("unix:{}", path.display()),
+            #[cfg(unix)]
+            Ok(LocalAddr::Unix(None)) => String::from("unix:?"),
+            Err(_) => String::from("?"),
+        }
+    }
+
+    // 接受连接，返回客户端地址
+    pub async fn accept(&self) -> io::Result<(Connection, PeerAddr)> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (stream, addr) = listener.accept().await?;
+                // 双栈监听时IPv4客户端的地址是 ::ffff:a.b.c.d 的形式
+                let addr = SocketAddr::new(addr.ip().to_canonical(), addr.port());
+                Ok((Connection::Plain(stream), PeerAddr::Tcp(addr)))
+            }
+            #[cfg(unix)]
+            Listener::Unix(listener) => {
+                let (stream, _) = listener.accept().await?;
+                let cred = stream.peer_cred().ok();
+                Ok((Connection::Unix(stream), PeerAddr::Unix(cred)))
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+impl AsRawFd for Listener {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            Listener::Tcp(listener) => listener.as_raw_fd(),
+            Listener::Unix(listener) => listener.as_raw_fd(),
+        }
+    }
+}
+
+// 热重启时优先使用旧进程传递的相同地址的套接字
+fn open(addr: &ListenAddr, others: &[ListenAddr], unix_mode: Option<u32>) -> io::Result<Listener> {
+    #[cfg(unix)]
+    if let Some(fd) = take_inherited(addr) {
+        return from_fd(fd);
+    }
+    match addr {
+        ListenAddr::Tcp(socket_addr) => {
+            // [::]默认同时接受IPv4，同一端口上还监听了IPv4地址时只接受IPv6，否则会冲突
+            let only_v6 = !socket_addr.ip().is_unspecified()
+                || others.iter().any(|other| match other {
+                    ListenAddr::Tcp(other) => other.is_ipv4() && other.port() == socket_addr.port(),
+                    #[cfg(unix)]
+                    _ => false,
+                });
+            #[cfg(not(unix))]
+            let _ = unix_mode;
+            bind_tcp(*socket_addr, only_v6).map(Listener::Tcp)
+        }
+        #[cfg(unix)]
+        ListenAddr::Unix(path) => bind_unix(path, unix_mode).map(Listener::Unix),
+        #[cfg(unix)]
+        ListenAddr::Fd(fd) => from_fd(*fd),
+    }
+}
+
+fn bind_tcp(addr: SocketAddr, only_v6: bool) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(only_v6)?;
+    }
+    // 与TcpListener::bind一致，重启时不受TIME_WAIT影响
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    TcpListener::from_std(socket.into())
+}
+
+// 删除上次运行留下的套接字文件后绑定，并设置文件权限
+#[cfg(unix)]
+fn bind_unix(path: &std::path::Path, mode: Option<u32>) -> io::Result<UnixListener> {
+    use std::fs;
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+    use std::os::unix::net::UnixStream;
+
+    match fs::symlink_metadata(path) {
+        // 还能连接上说明有其他进程在使用，只删除没有进程监听的套接字文件
+        Ok(metadata) if metadata.file_type().is_socket() => match UnixStream::connect(path) {
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    "套接字正在被其他进程使用",
+                ))
+            }
+            Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => fs::remove_file(path)?,
+            Err(err) => return Err(err),
+        },
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "文件已存在且不是套接字",
+            ))
+        }
+        Err(_) => {}
+    }
+    let listener = match mode {
+        Some(mode) => {
+            // 绑定时文件就以限定的权限创建，不会有先以默认权限存在的时间窗口
+            let _umask = Umask::set(!mode & 0o777);
+            UnixListener::bind(path)?
+        }
+        None => UnixListener::bind(path)?,
+    };
+    if let Some(mode) = mode {
+        // umask只能去掉权限位，再设置一次确保与配置一致
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    }
+    Ok(listener)
+}
+
+// 临时修改进程的umask，离开作用域时恢复
+// umask是进程级的，只在启动时绑定监听地址期间使用
+#[cfg(unix)]
+struct Umask(libc::mode_t);
+
+#[cfg(unix)]
+impl Umask {
+    fn set(mask: u32) -> Self {
+        // SAFETY: umask只修改进程的文件创建掩码，总是成功
+        Self(unsafe { libc::umask(mask as libc::mode_t) })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Umask {
+    fn drop(&mut self) {
+        // SAFETY: 同上
+        unsafe {
+            libc::umask(self.0);
+        }
+    }
+}
+
+// 使用已经打开的监听套接字，按本地地址区分TCP和Unix套接字
+#[cfg(unix)]
+fn from_fd(fd: RawFd) -> io::Result<Listener> {
+    // 先借用检查，不是套接字时不关闭fd
+    let local = match local_addr(fd) {
+        Some(local) => local,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "不是套接字")),
+    };
+    // SAFETY: fd由systemd、旧进程或命令行指定，此后由Listener独占
+    let socket = unsafe { Socket::from_raw_fd(fd) };
+    if socket.r#type()? != Type::STREAM {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "不是流式套接字",
+        ));
+    }
+    if !is_listening(&socket)? {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "套接字没有在监听",
+        ));
+    }
+    socket.set_nonblocking(true)?;
+    if local.is_unix() {
+        UnixListener::from_std(socket.into()).map(Listener::Unix)
+    } else {
+        TcpListener::from_std(socket.into()).map(Listener::Tcp)
+    }
+}
+
+// 检查SO_ACCEPTCONN，已连接的套接字不能用于接受连接
+#[cfg(unix)]
+fn is_listening(socket: &Socket) -> io::Result<bool> {
+    let mut value: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    // SAFETY: value和len指向有效的内存，长度与value一致
+    let result = unsafe {
+        libc::getsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_ACCEPTCONN,
+            &mut value as *mut libc::c_int as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(value != 0)
+}
+
+// 热重启，使用相同的参数启动新进程，监听套接字通过环境变量中的fd传递
+// 新进程在检查时间内没有退出才算启动成功
+#[cfg(unix)]
+pub async fn spawn_successor(fds: &[RawFd]) -> Result<()> {
+    use std::os::unix::process::CommandExt;
+    use std::process::Command;
+
+    let list: Vec<String> = fds.iter().map(|fd| fd.to_string()).collect();
+    let mut command = Command::new(std::env::current_exe()?);
+    command
+        .args(std::env::args_os().skip(1))
+        .env(INHERIT_ENV, list.join(","));
+    let fds = fds.to_vec();
+    // SAFETY: fork后只调用fcntl，不分配内存
+    unsafe {
+        command.pre_exec(move || {
+            for &fd in &fds {
+                let flags = libc::fcntl(fd, libc::F_GETFD);
+                if flags < 0 || libc::fcntl(fd, libc::F_SETFD, flags & !libc::FD_CLOEXEC) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+    let mut child = command.spawn()?;
+    tokio::time::sleep(SUCCESSOR_CHECK).await;
+    match child.try_wait()? {
+        Some(status) => Fail::from(format!("新进程已退出: {}", status)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("listener-{}-{}", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[tokio::test]
+    async fn local_addr_kind() {
+        let tcp =
+            Listener::bind(&ListenAddr::Tcp("127.0.0.1:0".parse().unwrap()), &[], None).unwrap();
+        let local = tcp.local_addr().unwrap();
+        assert!(!local.is_unix());
+        assert!(local.port().is_some_and(|port| port != 0));
+
+        let path = temp_path("kind.sock");
+        let unix = Listener::bind(&ListenAddr::Unix(path.clone()), &[], None).unwrap();
+        let local = unix.local_addr().unwrap();
+        assert_eq!(local, LocalAddr::Unix(Some(path.clone())));
+        assert!(local.is_unix());
+        assert_eq!(local.port(), None);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn unix_socket_mode() {
+        let path = temp_path("mode.sock");
+        let _listener = bind_unix(&path, Some(0o600)).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn replace_only_stale_socket() {
+        let path = temp_path("stale.sock");
+        // 上次运行留下的套接字文件，没有进程监听
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        assert!(Path::new(&path).exists());
+        let listener = bind_unix(&path, None).unwrap();
+
+        // 正在使用的套接字不删除
+        let err = bind_unix(&path, None).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        drop(listener);
+        fs::remove_file(&path).unwrap();
+
+        // 普通文件不删除
+        fs::write(&path, "data").unwrap();
+        let err = bind_unix(&path, None).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "data");
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,139 @@
+use crate::utils::{html_escape, http_date, url_encode};
+use serde_json::json;
+use std::cmp::Ordering;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+
+// 目录中的一项
+pub struct Entry {
+    pub name: String,
+    pub is_dir: bool,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+// 排序方式
+#[derive(Clone, Copy, PartialEq)]
+pub enum SortKey {
+    Name,
+    Size,
+    Modified,
+}
+
+impl SortKey {
+    pub fn parse(key: &str) -> Self {
+        match key {
+            "size" => SortKey::Size,
+            "mtime" | "modified" => SortKey::Modified,
+            _ => SortKey::Name,
+        }
+    }
+}
+
+// 读取目录，filter返回false的项被忽略
+pub fn read_entries<F>(dir: &Path, filter: F) -> io::Result<Vec<Entry>>
+where
+    F: Fn(&str) -> bool,
+{
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !filter(&name) {
+            continue;
+        }
+        // 跟随符号链接获取目标的信息
+        let metadata = match fs::metadata(entry.path()) {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        entries.push(Entry {
+            name,
+            is_dir: metadata.is_dir(),
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+        });
+    }
+    Ok(entries)
+}
+
+// 目录始终排在文件前面
+pub fn sort(entries: &mut [Entry], key: SortKey, descending: bool) {
+    entries.sort_by(|a, b| {
+        let order = match key {
+            SortKey::Name => a.name.cmp(&b.name),
+            SortKey::Size => a.size.cmp(&b.size).then_with(|| a.name.cmp(&b.name)),
+            SortKey::Modified => a
+                .modified
+                .cmp(&b.modified)
+                .then_with(|| a.name.cmp(&b.name)),
+        };
+        let order = if descending { order.reverse() } else { order };
+        match (a.is_dir, b.is_dir) {
+            (true, false) => Ordering::Less,
+            (false, true) => Ordering::Greater,
+            _ => order,
+        }
+    });
+}
+
+pub fn render_html(path: &str, entries: &[Entry], key: SortKey, descending: bool) -> String {
+    let title = html_escape(path);
+    // 表头链接，点击当前排序列时切换顺序
+    let header = |name: &str, sort: SortKey, param: &str| {
+        let order = if sort == key && !descending {
+            "desc"
+        } else {
+            "asc"
+        };
+        format!(
+            "<th><a href=\"?sort={}&order={}\">{}</a></th>",
+            param, order, name
+        )
+    };
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Index of {0}</title>\n</head>\n<body>\n<h1>Index of {0}</h1>\n<table>\n<tr>{1}{2}{3}</tr>\n",
+        title,
+        header("Name", SortKey::Name, "name"),
+        header("Size", SortKey::Size, "size"),
+        header("Last Modified", SortKey::Modified, "mtime"),
+    );
+    if path != "/" {
+        html.push_str("<tr><td><a href=\"../\">../</a></td><td></td><td></td></tr>\n");
+    }
+    for entry in entries {
+        let suffix = if entry.is_dir { "/" } else { "" };
+        html.push_str(&format!(
+            "<tr><td><a href=\"{}{}\">{}{}</a></td><td>{}</td><td>{}</td></tr>\n",
+            url_encode(&entry.name),
+            suffix,
+            html_escape(&entry.name),
+            suffix,
+            if entry.is_dir {
+                String::from("-")
+            } else {
+                entry.size.to_string()
+            },
+            entry.modified.map(http_date).unwrap_or_default(),
+        ));
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+pub fn render_json(path: &str, entries: &[Entry]) -> String {
+    let entries: Vec<_> = entries
+        .iter()
+        .map(|entry| {
+            json!({
+                "name": entry.name,
+                "type": if entry.is_dir { "directory" } else { "file" },
+                "size": entry.size,
+                "modified": entry.modified.map(http_date),
+            })
+        })
+        .collect();
+    json!({ "path": path, "entries": entries }).to_string()
+}
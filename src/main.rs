@@ -1,25 +1,139 @@
-// 服务器模块
-mod server;
-// 请求模块
-mod request;
-// 响应模块
-mod response;
-// 路由模块
-mod router;
-// 处理器模块
-mod handler;
-// 错误处理模块
-mod error;
-// 工具模块
-mod utils;
-// 常量
-mod constant;
-
-use crate::server::{HttpSettings, Server};
+use my_http_server::compression::Compression;
+use my_http_server::config::Config;
+use my_http_server::error::{Fail, Result};
+use my_http_server::handler::StaticHandler;
+use my_http_server::listener::ListenAddr;
+use my_http_server::proxy::ProxyProtocol;
+use my_http_server::redirect::{Hsts, HttpsRedirect};
+use my_http_server::rewrite::RewriteRules;
+use my_http_server::router::Router;
+use my_http_server::server::{HttpSettings, Server};
+use my_http_server::tls::{self, CertSource, CertStore};
+use my_http_server::vhost::VirtualHosts;
+use std::sync::Arc;
+use std::{env, process};
 
 #[tokio::main]
 async fn main() {
-    let http_settings = HttpSettings::new();
-    let server = Server::new("127.0.0.1:8080", http_settings);
-    server.run().await.unwrap();
+    if let Err(err) = run().await {
+        println!("{}", err);
+        process::exit(1);
+    }
+}
+
+async fn run() -> Result<()> {
+    let config = Config::from_args(env::args())?;
+    let mut http_settings = HttpSettings::new();
+    http_settings.http2 = config.http2;
+    let https = config.tls_cert.is_some() || !config.tls_sni.is_empty();
+    if config.redirect_addr.is_some() && !https {
+        return Fail::from("--redirect-addr 需要同时配置服务端证书");
+    }
+    let static_handler = if config.embedded && !config.roots.is_empty() {
+        StaticHandler::embedded()
+            .live(config.live)
+            .roots(config.roots.clone())
+    } else if config.embedded {
+        StaticHandler::embedded().live(config.live)
+    } else {
+        StaticHandler::with_roots(config.roots.clone())
+    };
+    // 没有匹配到虚拟主机时使用roots
+    let mut hosts = VirtualHosts::new().default_host(router(&config, static_handler)?);
+    for (host, root) in &config.vhosts {
+        let static_handler = StaticHandler::with_roots(vec![root.clone()]);
+        hosts = hosts.host(host, router(&config, static_handler)?);
+    }
+    let mut server = Server::new(&config.addrs[0], http_settings, hosts)?;
+    for addr in &config.addrs[1..] {
+        server = server.listen(addr)?;
+    }
+    if let Some(mode) = config.unix_mode {
+        server = server.unix_mode(mode);
+    }
+    if let Some(redirect_addr) = &config.redirect_addr {
+        server = server.http_listener(redirect_addr)?;
+    }
+    if !config.proxy_protocol.is_empty() {
+        let mut proxy = ProxyProtocol::new();
+        for cidr in &config.proxy_protocol {
+            proxy = proxy.trust(cidr)?;
+        }
+        server = server.proxy_protocol(proxy);
+    }
+    let mut certs = Vec::new();
+    match (config.tls_cert, config.tls_key) {
+        (Some(cert), Some(key)) => certs.push(CertSource {
+            host: None,
+            cert,
+            key,
+        }),
+        (None, None) => {}
+        _ => return Fail::from("--tls-cert 和 --tls-key 需要同时指定"),
+    }
+    certs.extend(config.tls_sni);
+    if !certs.is_empty() {
+        let store = Arc::new(CertStore::new(certs)?);
+        store.watch();
+        let client_auth = config
+            .tls_client_ca
+            .as_deref()
+            .map(|ca| (ca, config.tls_client_auth));
+        server = server.tls(tls::server_config(store, client_auth)?);
+    } else if config.tls_client_ca.is_some() {
+        return Fail::from("--tls-client-ca 需要同时配置服务端证书");
+    }
+    server.run().await
+}
+
+// 每个主机使用相同的中间件，静态资源目录不同
+fn router(config: &Config, static_handler: StaticHandler) -> Result<Router> {
+    let mut router = Router::new();
+    // 重定向在重写之前，按客户端请求的原始路径判断
+    if config.redirect_addr.is_some() {
+        // 重定向到第一个TCP监听地址的端口
+        let mut port = None;
+        for addr in &config.addrs {
+            port = port.or(ListenAddr::parse(addr)?.iter().find_map(|a| a.port()));
+        }
+        let port = match port {
+            Some(port) => port,
+            None => return Fail::from("--redirect-addr 需要HTTPS监听TCP地址"),
+        };
+        let mut redirect = HttpsRedirect::new(port);
+        for prefix in &config.redirect_exempt {
+            redirect = redirect.exempt(prefix);
+        }
+        router = router.middleware(redirect);
+    }
+    if let Some(hsts) = &config.hsts {
+        router = router.middleware(Hsts::new(hsts));
+    }
+    if let Some(rewrite) = &config.rewrite {
+        router = router.middleware(RewriteRules::from_file(rewrite)?);
+    }
+    if config.compress {
+        router = router.middleware(
+            Compression::new()
+                .min_size(config.compress_min_size)
+                .gzip_level(config.gzip_level)
+                .brotli_level(config.brotli_level),
+        );
+    }
+    let mut static_handler = static_handler
+        .symlinks(config.symlinks)
+        .dotfiles(config.dotfiles)
+        .listing(config.listing)
+        .precompressed(config.precompressed)
+        .sniff(config.sniff)
+        .etag(config.etag)
+        .file_cache(config.file_cache)
+        .sendfile_threshold(config.sendfile_threshold as u64);
+    for (extension, mime) in &config.mime_types {
+        static_handler = static_handler.mime_type(extension, mime);
+    }
+    for (pattern, cache_control) in &config.cache_control {
+        static_handler = static_handler.cache_control(pattern, cache_control);
+    }
+    Ok(router.fallback(static_handler))
 }
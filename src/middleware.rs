@@ -0,0 +1,44 @@
+use crate::request::HttpRequest;
+use crate::response::HttpResponse;
+use std::sync::Arc;
+
+// 中间件接口，通过next继续执行后续的中间件和处理器
+pub trait Middleware: Send + Sync {
+    fn handle(&self, req: HttpRequest, next: Next) -> HttpResponse<'static>;
+}
+
+// 普通函数和闭包也可以作为中间件
+impl<F> Middleware for F
+where
+    F: Fn(HttpRequest, Next) -> HttpResponse<'static> + Send + Sync,
+{
+    fn handle(&self, req: HttpRequest, next: Next) -> HttpResponse<'static> {
+        self(req, next)
+    }
+}
+
+// 剩余的中间件链
+pub struct Next<'r> {
+    middlewares: &'r [Arc<dyn Middleware>],
+    endpoint: &'r (dyn Fn(HttpRequest) -> HttpResponse<'static> + Sync),
+}
+
+impl<'r> Next<'r> {
+    pub fn new(
+        middlewares: &'r [Arc<dyn Middleware>],
+        endpoint: &'r (dyn Fn(HttpRequest) -> HttpResponse<'static> + Sync),
+    ) -> Self {
+        Self {
+            middlewares,
+            endpoint,
+        }
+    }
+
+    // 执行下一个中间件，全部执行完后交给处理器
+    pub fn run(self, req: HttpRequest) -> HttpResponse<'static> {
+        match self.middlewares.split_first() {
+            Some((first, rest)) => first.handle(req, Next::new(rest, self.endpoint)),
+            None => (self.endpoint)(req),
+        }
+    }
+}
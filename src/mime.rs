@@ -0,0 +1,285 @@
+use crate::constant;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+// 扩展名对应的MIME类型
+const MIME_TABLE: &[(&str, &str)] = &[
+    // 文本
+    ("html", constant::TEXT_HTML),
+    ("htm", constant::TEXT_HTML),
+    ("css", constant::TEXT_CSS),
+    ("js", constant::TEXT_JAVASCRIPT),
+    ("mjs", constant::TEXT_JAVASCRIPT),
+    ("txt", constant::TEXT_PLAIN),
+    ("csv", "text/csv"),
+    ("md", "text/markdown"),
+    ("xml", "application/xml"),
+    ("json", constant::APPLICATION_JSON),
+    ("map", constant::APPLICATION_JSON),
+    ("webmanifest", "application/manifest+json"),
+    ("ics", "text/calendar"),
+    // 图片
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("avif", "image/avif"),
+    ("svg", "image/svg+xml"),
+    ("ico", "image/x-icon"),
+    ("bmp", "image/bmp"),
+    ("tif", "image/tiff"),
+    ("tiff", "image/tiff"),
+    // 字体
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("ttf", "font/ttf"),
+    ("otf", "font/otf"),
+    ("eot", "application/vnd.ms-fontobject"),
+    // 音视频
+    ("mp3", "audio/mpeg"),
+    ("ogg", "audio/ogg"),
+    ("oga", "audio/ogg"),
+    ("wav", "audio/wav"),
+    ("flac", "audio/flac"),
+    ("m4a", "audio/mp4"),
+    ("aac", "audio/aac"),
+    ("mp4", "video/mp4"),
+    ("m4v", "video/mp4"),
+    ("webm", "video/webm"),
+    ("ogv", "video/ogg"),
+    ("mov", "video/quicktime"),
+    ("vtt", "text/vtt"),
+    // 其他
+    ("wasm", "application/wasm"),
+    ("pdf", "application/pdf"),
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("tar", "application/x-tar"),
+    ("7z", "application/x-7z-compressed"),
+    ("rar", "application/vnd.rar"),
+    ("doc", "application/msword"),
+    (
+        "docx",
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    ),
+    ("xls", "application/vnd.ms-excel"),
+    (
+        "xlsx",
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    ),
+    ("ppt", "application/vnd.ms-powerpoint"),
+    (
+        "pptx",
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+    ),
+];
+
+// 文件头特征
+const MAGIC_TABLE: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+    (b"\0asm", "application/wasm"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\x1f\x8b", "application/gzip"),
+    (b"wOFF", "font/woff"),
+    (b"wOF2", "font/woff2"),
+    (b"ID3", "audio/mpeg"),
+    (b"OggS", "audio/ogg"),
+    (b"\x1aE\xdf\xa3", "video/webm"),
+];
+
+// 根据扩展名判断MIME类型，支持自定义和内容嗅探
+#[derive(Clone, Debug, Default)]
+pub struct MimeTypes {
+    // 自定义的扩展名，优先于内置表
+    overrides: BTreeMap<String, String>,
+    // 没有扩展名时是否根据内容判断
+    sniff: bool,
+}
+
+#[allow(dead_code)]
+impl MimeTypes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, extension: &str, mime: &str) {
+        self.overrides.insert(
+            extension.trim_start_matches('.').to_lowercase(),
+            mime.to_string(),
+        );
+    }
+
+    pub fn set_sniff(&mut self, sniff: bool) {
+        self.sniff = sniff;
+    }
+
+    // 返回完整的Content-Type，文本类型带上charset
+    pub fn guess(&self, path: &Path, head: &[u8]) -> String {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+        let mime = match &extension {
+            Some(ext) => match self.overrides.get(ext) {
+                Some(mime) => return with_charset(mime),
+                None => lookup(ext),
+            },
+            None if self.sniff => sniff(head),
+            None => None,
+        };
+        with_charset(mime.unwrap_or(constant::APPLICATION_OCTET_STREAM))
+    }
+}
+
+pub fn lookup(extension: &str) -> Option<&'static str> {
+    MIME_TABLE
+        .iter()
+        .find(|(ext, _)| ext.eq_ignore_ascii_case(extension))
+        .map(|(_, mime)| *mime)
+}
+
+// 根据文件头判断类型
+fn sniff(head: &[u8]) -> Option<&'static str> {
+    let head = &head[..head.len().min(512)];
+    if let Some((_, mime)) = MAGIC_TABLE
+        .iter()
+        .find(|(magic, _)| head.starts_with(magic))
+    {
+        return Some(mime);
+    }
+    if head.len() >= 12 && &head[0..4] == b"RIFF" && &head[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+    if head.len() >= 8 && &head[4..8] == b"ftyp" {
+        return Some("video/mp4");
+    }
+    let text = String::from_utf8_lossy(head);
+    let start = text.trim_start().to_lowercase();
+    if start.starts_with("<!doctype html") || start.starts_with("<html") {
+        Some(constant::TEXT_HTML)
+    } else if start.starts_with("<?xml") {
+        Some("application/xml")
+    } else if !head.contains(&0) && is_utf8(head) {
+        Some(constant::TEXT_PLAIN)
+    } else {
+        None
+    }
+}
+
+// 允许末尾有被截断的字符
+fn is_utf8(head: &[u8]) -> bool {
+    match std::str::from_utf8(head) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    }
+}
+
+// 文本类型加上utf-8编码
+fn with_charset(mime: &str) -> String {
+    let textual = mime.starts_with("text/")
+        || mime == constant::APPLICATION_JSON
+        || mime == "application/xml"
+        || mime == "application/manifest+json"
+        || mime == "image/svg+xml";
+    if textual && !mime.contains("charset") {
+        format!("{}; charset=utf-8", mime)
+    } else {
+        mime.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sniffed(head: &[u8]) -> String {
+        let mut types = MimeTypes::new();
+        types.set_sniff(true);
+        types.guess(Path::new("file"), head)
+    }
+
+    #[test]
+    fn guess_by_extension() {
+        let types = MimeTypes::new();
+        assert_eq!(types.guess(Path::new("a.PNG"), b""), "image/png");
+        assert_eq!(
+            types.guess(Path::new("a/b.css"), b""),
+            "text/css; charset=utf-8"
+        );
+        assert_eq!(
+            types.guess(Path::new("a.unknown"), b""),
+            "application/octet-stream"
+        );
+        // 没有开启嗅探时不看内容
+        assert_eq!(
+            types.guess(Path::new("a"), b"<html>"),
+            "application/octet-stream"
+        );
+    }
+
+    #[test]
+    fn custom_types() {
+        let mut types = MimeTypes::new();
+        types.insert(".MJS", "application/javascript");
+        types.insert("log", "text/plain; charset=gbk");
+        assert_eq!(
+            types.guess(Path::new("a.mjs"), b""),
+            "application/javascript"
+        );
+        assert_eq!(
+            types.guess(Path::new("a.log"), b""),
+            "text/plain; charset=gbk"
+        );
+    }
+
+    #[test]
+    fn sniff_magic_numbers() {
+        assert_eq!(sniffed(b"\x89PNG\r\n\x1a\n\0\0"), "image/png");
+        assert_eq!(sniffed(b"\xff\xd8\xff\xe0"), "image/jpeg");
+        assert_eq!(sniffed(b"GIF89a"), "image/gif");
+        assert_eq!(sniffed(b"%PDF-1.7"), "application/pdf");
+        assert_eq!(sniffed(b"\0asm\x01\0\0\0"), "application/wasm");
+        assert_eq!(sniffed(b"RIFF\0\0\0\0WEBPVP8 "), "image/webp");
+        assert_eq!(sniffed(b"\0\0\0\x18ftypmp42"), "video/mp4");
+        assert_eq!(
+            sniffed(b"  <!DOCTYPE html><html>"),
+            "text/html; charset=utf-8"
+        );
+        assert_eq!(
+            sniffed(b"<?xml version=\"1.0\"?>"),
+            "application/xml; charset=utf-8"
+        );
+        assert_eq!(sniffed("纯文本".as_bytes()), "text/plain; charset=utf-8");
+        // 末尾被截断的UTF-8字符仍然是文本
+        assert_eq!(
+            sniffed(&"文本".as_bytes()[..5]),
+            "text/plain; charset=utf-8"
+        );
+        assert_eq!(sniffed(b"\0\x01\x02\x03"), "application/octet-stream");
+        assert_eq!(sniffed(b"\xff\xfe\xfd"), "application/octet-stream");
+    }
+
+    #[test]
+    fn charset_suffix() {
+        assert_eq!(with_charset("text/plain"), "text/plain; charset=utf-8");
+        assert_eq!(
+            with_charset("application/json"),
+            "application/json; charset=utf-8"
+        );
+        assert_eq!(
+            with_charset("image/svg+xml"),
+            "image/svg+xml; charset=utf-8"
+        );
+        assert_eq!(
+            with_charset("text/html; charset=gbk"),
+            "text/html; charset=gbk"
+        );
+        assert_eq!(with_charset("image/png"), "image/png");
+        assert_eq!(with_charset("application/wasm"), "application/wasm");
+    }
+}
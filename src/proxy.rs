@@ -0,0 +1,368 @@
+use crate::connection::Connection;
+use crate::error::{Fail, Result};
+use crate::listener::PeerAddr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+// 读取PROXY协议头的超时时间
+const HEADER_TIMEOUT: Duration = Duration::from_secs(10);
+
+// v1协议头的最大长度，包括结尾的\r\n
+const V1_MAX_LEN: usize = 107;
+
+// v2协议头的签名
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+// 代理转发的连接信息，作为请求的扩展传给handler
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub struct ProxyInfo {
+    // 客户端地址
+    pub source: SocketAddr,
+    // 客户端连接的代理地址
+    pub destination: SocketAddr,
+}
+
+// 负载均衡通过PROXY协议v1/v2传递客户端地址
+// 只有来自信任地址的连接需要发送协议头，其他连接按直接连接处理
+#[derive(Clone, Debug, Default)]
+pub struct ProxyProtocol {
+    // 信任的网段和前缀长度
+    trusted: Vec<(IpAddr, u8)>,
+}
+
+impl ProxyProtocol {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 信任的地址，支持单个IP和CIDR，如 10.0.0.0/8
+    pub fn trust(mut self, cidr: &str) -> Result<Self> {
+        let (ip, prefix) = match cidr.split_once('/') {
+            Some((ip, prefix)) => (ip, Some(prefix)),
+            None => (cidr, None),
+        };
+        let ip: IpAddr = match ip.trim().parse() {
+            Ok(ip) => ip,
+            Err(_) => return Fail::from(format!("无效的信任地址: {}", cidr)),
+        };
+        let width = if ip.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix.map(|prefix| prefix.trim().parse::<u8>()) {
+            None => width,
+            Some(Ok(prefix)) if prefix <= width => prefix,
+            Some(_) => return Fail::from(format!("无效的信任地址: {}", cidr)),
+        };
+        self.trusted.push((ip, prefix));
+        Ok(self)
+    }
+
+    // Unix套接字的连接没有IP，不按信任地址匹配
+    pub fn trusted(&self, peer: &PeerAddr) -> bool {
+        match peer.ip() {
+            Some(ip) => self
+                .trusted
+                .iter()
+                .any(|&(network, prefix)| contains(network, prefix, ip)),
+            None => false,
+        }
+    }
+
+    // 读取协议头，只读取协议头本身，之后的数据留给HTTP或TLS
+    // 代理自己的连接(LOCAL)和未知的地址类型返回None，使用连接的地址
+    pub async fn accept(&self, stream: &mut Connection) -> Result<Option<ProxyInfo>> {
+        match tokio::time::timeout(HEADER_TIMEOUT, read_header(stream)).await {
+            Ok(result) => result,
+            Err(_) => Fail::from("读取PROXY协议头超时"),
+        }
+    }
+}
+
+// 比较前缀长度内的位，IPv4和IPv6不互相匹配
+fn contains(network: IpAddr, prefix: u8, ip: IpAddr) -> bool {
+    let (network, ip, width) = match (network, ip) {
+        (IpAddr::V4(network), IpAddr::V4(ip)) => {
+            (u32::from(network) as u128, u32::from(ip) as u128, 32)
+        }
+        (IpAddr::V6(network), IpAddr::V6(ip)) => (u128::from(network), u128::from(ip), 128),
+        _ => return false,
+    };
+    // 前缀为0时右移128位会溢出，此时匹配所有地址
+    let shift = width - prefix as u32;
+    network.checked_shr(shift).unwrap_or(0) == ip.checked_shr(shift).unwrap_or(0)
+}
+
+// 按开头的字节区分v1和v2，信任的连接没有协议头时拒绝
+async fn read_header<R: AsyncRead + Unpin>(stream: &mut R) -> Result<Option<ProxyInfo>> {
+    let mut start = [0u8; 6];
+    stream.read_exact(&mut start).await?;
+    if &start == b"PROXY " {
+        read_v1(stream).await
+    } else if start[..] == V2_SIGNATURE[..6] {
+        read_v2(stream).await
+    } else {
+        Fail::from("缺少PROXY协议头")
+    }
+}
+
+// 文本格式，如 PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\n
+async fn read_v1<R: AsyncRead + Unpin>(stream: &mut R) -> Result<Option<ProxyInfo>> {
+    // 逐字节读取，不能读到协议头之后的数据
+    let mut line = b"PROXY ".to_vec();
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= V1_MAX_LEN {
+            return Fail::from("PROXY协议头过长");
+        }
+        line.push(stream.read_u8().await?);
+    }
+    let line = match std::str::from_utf8(&line[..line.len() - 2]) {
+        Ok(line) => line,
+        Err(_) => return Fail::from("无效的PROXY协议头"),
+    };
+    match line.split(' ').collect::<Vec<_>>()[..] {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", family @ ("TCP4" | "TCP6"), src, dst, src_port, dst_port] => {
+            let ipv6 = family == "TCP6";
+            Ok(Some(ProxyInfo {
+                source: parse_v1_addr(src, src_port, ipv6)?,
+                destination: parse_v1_addr(dst, dst_port, ipv6)?,
+            }))
+        }
+        _ => Fail::from(format!("无效的PROXY协议头: {}", line)),
+    }
+}
+
+fn parse_v1_addr(ip: &str, port: &str, ipv6: bool) -> Result<SocketAddr> {
+    match (ip.parse::<IpAddr>(), port.parse::<u16>()) {
+        (Ok(ip), Ok(port)) if ip.is_ipv6() == ipv6 => Ok(SocketAddr::new(ip.to_canonical(), port)),
+        _ => Fail::from(format!("无效的PROXY协议地址: {} {}", ip, port)),
+    }
+}
+
+// 二进制格式，签名之后是版本和命令、地址族和协议、地址部分的长度
+async fn read_v2<R: AsyncRead + Unpin>(stream: &mut R) -> Result<Option<ProxyInfo>> {
+    let mut head = [0u8; 10];
+    stream.read_exact(&mut head).await?;
+    if head[..6] != V2_SIGNATURE[6..] {
+        return Fail::from("无效的PROXY协议头");
+    }
+    if head[6] >> 4 != 2 {
+        return Fail::from("不支持的PROXY协议版本");
+    }
+    let len = u16::from_be_bytes([head[8], head[9]]) as usize;
+    let mut payload = vec![0u8; len];
+    stream.read_exact(&mut payload).await?;
+    match head[6] & 0x0f {
+        // LOCAL，如负载均衡的健康检查
+        0x0 => return Ok(None),
+        0x1 => {}
+        _ => return Fail::from("无效的PROXY协议命令"),
+    }
+    // 地址之后的TLV不需要处理
+    let (source, destination) = match head[7] >> 4 {
+        0x1 if payload.len() >= 12 => {
+            let ip = |i: usize| {
+                IpAddr::V4(Ipv4Addr::new(
+                    payload[i],
+                    payload[i + 1],
+                    payload[i + 2],
+                    payload[i + 3],
+                ))
+            };
+            (
+                SocketAddr::new(ip(0), u16::from_be_bytes([payload[8], payload[9]])),
+                SocketAddr::new(ip(4), u16::from_be_bytes([payload[10], payload[11]])),
+            )
+        }
+        0x2 if payload.len() >= 36 => {
+            let ip = |i: usize| {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(&payload[i..i + 16]);
+                IpAddr::V6(Ipv6Addr::from(octets)).to_canonical()
+            };
+            (
+                SocketAddr::new(ip(0), u16::from_be_bytes([payload[32], payload[33]])),
+                SocketAddr::new(ip(16), u16::from_be_bytes([payload[34], payload[35]])),
+            )
+        }
+        // AF_UNSPEC和AF_UNIX没有IP地址
+        0x0 | 0x3 => return Ok(None),
+        _ => return Fail::from("无效的PROXY协议地址"),
+    };
+    Ok(Some(ProxyInfo {
+        source,
+        destination,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn parse(data: &[u8]) -> Result<Option<ProxyInfo>> {
+        read_header(&mut &data[..]).await
+    }
+
+    fn v2(command: u8, family: u8, payload: &[u8]) -> Vec<u8> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.push(0x20 | command);
+        header.push(family);
+        header.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        header.extend_from_slice(payload);
+        header
+    }
+
+    #[tokio::test]
+    async fn read_v1_header() {
+        let mut data: &[u8] = b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\nGET / HTTP/1.1\r\n";
+        let info = read_header(&mut data).await.unwrap().unwrap();
+        assert_eq!(info.source, "192.0.2.1:56324".parse().unwrap());
+        assert_eq!(info.destination, "198.51.100.1:443".parse().unwrap());
+        // 协议头之后的数据留在流中
+        assert_eq!(data, b"GET / HTTP/1.1\r\n");
+
+        let info = parse(b"PROXY TCP6 2001:db8::1 ::ffff:10.0.0.1 1 2\r\n")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.source, "[2001:db8::1]:1".parse().unwrap());
+        assert_eq!(info.destination, "10.0.0.1:2".parse().unwrap());
+        assert!(parse(b"PROXY UNKNOWN\r\n").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn reject_invalid_v1_header() {
+        for data in [
+            &b"PROXY TCP4 192.0.2.1 198.51.100.1 56324\r\n"[..],
+            b"PROXY TCP4 2001:db8::1 198.51.100.1 1 2\r\n",
+            b"PROXY TCP6 192.0.2.1 198.51.100.1 1 2\r\n",
+            b"PROXY TCP4 192.0.2.1 198.51.100.1 1 70000\r\n",
+            b"PROXY UDP4 192.0.2.1 198.51.100.1 1 2\r\n",
+            b"PROXY TCP4  192.0.2.1 198.51.100.1 1 2\r\n",
+            b"PROXY \xff\r\n",
+            b"GET / HTTP/1.1\r\n",
+        ] {
+            assert!(parse(data).await.is_err(), "{:?}", data);
+        }
+        // 没有结尾的\r\n时最多读取V1_MAX_LEN个字节
+        let long = [&b"PROXY "[..], &[b'1'; 200]].concat();
+        assert!(parse(&long).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn reject_truncated_header() {
+        let v1 = b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\n";
+        let mut payload = vec![192, 0, 2, 1, 198, 51, 100, 1];
+        payload.extend_from_slice(&56324u16.to_be_bytes());
+        payload.extend_from_slice(&443u16.to_be_bytes());
+        let v2 = v2(0x1, 0x11, &payload);
+        for len in 0..v1.len() {
+            assert!(parse(&v1[..len]).await.is_err());
+        }
+        for len in 0..v2.len() {
+            assert!(parse(&v2[..len]).await.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn read_v2_header() {
+        let mut payload = vec![192, 0, 2, 1, 198, 51, 100, 1];
+        payload.extend_from_slice(&56324u16.to_be_bytes());
+        payload.extend_from_slice(&443u16.to_be_bytes());
+        // 地址之后的TLV会被跳过
+        payload.extend_from_slice(&[0x04, 0x00, 0x01, 0xff]);
+        let mut data = v2(0x1, 0x11, &payload);
+        data.extend_from_slice(b"rest");
+        let mut stream = &data[..];
+        let info = read_header(&mut stream).await.unwrap().unwrap();
+        assert_eq!(info.source, "192.0.2.1:56324".parse().unwrap());
+        assert_eq!(info.destination, "198.51.100.1:443".parse().unwrap());
+        assert_eq!(stream, b"rest");
+
+        let mut payload = [0u8; 36];
+        payload[15] = 1;
+        payload[16..32].copy_from_slice(&"2001:db8::2".parse::<Ipv6Addr>().unwrap().octets());
+        payload[32..34].copy_from_slice(&1234u16.to_be_bytes());
+        payload[34..36].copy_from_slice(&80u16.to_be_bytes());
+        let info = parse(&v2(0x1, 0x21, &payload)).await.unwrap().unwrap();
+        assert_eq!(info.source, "[::1]:1234".parse().unwrap());
+        assert_eq!(info.destination, "[2001:db8::2]:80".parse().unwrap());
+    }
+
+    #[tokio::test]
+    async fn read_v2_without_address() {
+        // LOCAL命令和AF_UNSPEC、AF_UNIX使用连接的地址
+        assert!(parse(&v2(0x0, 0x11, &[0u8; 12])).await.unwrap().is_none());
+        assert!(parse(&v2(0x1, 0x00, &[])).await.unwrap().is_none());
+        assert!(parse(&v2(0x1, 0x31, &[0u8; 216])).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn reject_invalid_v2_header() {
+        // 签名后半部分错误
+        let mut data = v2(0x1, 0x11, &[0u8; 12]);
+        data[8] = b'X';
+        assert!(parse(&data).await.is_err());
+        // 版本不是2
+        let mut data = v2(0x1, 0x11, &[0u8; 12]);
+        data[12] = 0x11;
+        assert!(parse(&data).await.is_err());
+        // 未知的命令和地址族
+        assert!(parse(&v2(0x2, 0x11, &[0u8; 12])).await.is_err());
+        assert!(parse(&v2(0x1, 0x41, &[0u8; 12])).await.is_err());
+        // 地址部分太短
+        assert!(parse(&v2(0x1, 0x11, &[0u8; 11])).await.is_err());
+        assert!(parse(&v2(0x1, 0x21, &[0u8; 35])).await.is_err());
+    }
+
+    #[test]
+    fn trust_cidr() {
+        let proxy = ProxyProtocol::new()
+            .trust("10.0.0.0/8")
+            .unwrap()
+            .trust("192.0.2.7")
+            .unwrap()
+            .trust("2001:db8::/32")
+            .unwrap();
+        let tcp = |addr: &str| PeerAddr::Tcp(SocketAddr::new(addr.parse().unwrap(), 1));
+        assert!(proxy.trusted(&tcp("10.255.0.1")));
+        assert!(!proxy.trusted(&tcp("11.0.0.1")));
+        assert!(proxy.trusted(&tcp("192.0.2.7")));
+        assert!(!proxy.trusted(&tcp("192.0.2.8")));
+        assert!(proxy.trusted(&tcp("2001:db8:1::1")));
+        assert!(!proxy.trusted(&tcp("2001:db9::1")));
+        // IPv4和IPv6不互相匹配
+        assert!(!proxy.trusted(&tcp("::ffff:10.0.0.1")));
+        #[cfg(unix)]
+        assert!(!proxy.trusted(&PeerAddr::Unix(None)));
+    }
+
+    #[test]
+    fn cidr_prefix_edges() {
+        let any = "0.0.0.0".parse().unwrap();
+        assert!(contains(any, 0, "255.255.255.255".parse().unwrap()));
+        assert!(contains(
+            "::".parse().unwrap(),
+            0,
+            "2001:db8::1".parse().unwrap()
+        ));
+        assert!(!contains(any, 0, "::1".parse().unwrap()));
+        let network = "192.168.1.128".parse().unwrap();
+        assert!(contains(network, 25, "192.168.1.255".parse().unwrap()));
+        assert!(!contains(network, 25, "192.168.1.127".parse().unwrap()));
+    }
+
+    #[test]
+    fn reject_invalid_trust() {
+        for cidr in [
+            "",
+            "10.0.0.0/33",
+            "::/129",
+            "10.0.0/8",
+            "10.0.0.0/x",
+            "host",
+        ] {
+            assert!(ProxyProtocol::new().trust(cidr).is_err(), "{}", cidr);
+        }
+    }
+}
@@ -0,0 +1,371 @@
+use crate::caching::Validators;
+use crate::utils::parse_http_date;
+use std::fs::File;
+use std::io::{self, SeekFrom};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::sync::mpsc::{self, Receiver};
+
+// 单个请求中最多允许的范围数量，超出时忽略Range返回完整内容
+const MAX_RANGES: usize = 32;
+
+// 闭区间 [start, end]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    // Content-Range响应头
+    pub fn content_range(&self, total: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, total)
+    }
+}
+
+// Range请求头的解析结果
+#[derive(Debug, PartialEq)]
+pub enum RangeRequest {
+    // 没有或忽略Range，返回完整内容
+    Full,
+    // 需要返回的范围，已排序并合并重叠部分
+    Partial(Vec<ByteRange>),
+    // 所有范围都超出了文件大小
+    Unsatisfiable,
+}
+
+impl RangeRequest {
+    // 解析Range请求头，格式错误时按没有Range处理
+    pub fn parse(header: &str, total: u64) -> Self {
+        let specs = match header.trim().strip_prefix("bytes=") {
+            Some(specs) => specs,
+            None => return RangeRequest::Full,
+        };
+        let specs: Vec<&str> = specs
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .collect();
+        // 没有任何范围的Range属于格式错误
+        if specs.is_empty() {
+            return RangeRequest::Full;
+        }
+        let mut ranges = Vec::new();
+        for spec in specs {
+            let (start, end) = match spec.split_once('-') {
+                Some(pair) => pair,
+                None => return RangeRequest::Full,
+            };
+            let range = match (start.trim(), end.trim()) {
+                // 最后n个字节，如 -500
+                ("", suffix) => match suffix.parse::<u64>() {
+                    Ok(0) => None,
+                    Ok(n) if total > 0 => Some(ByteRange {
+                        start: total.saturating_sub(n),
+                        end: total - 1,
+                    }),
+                    Ok(_) => None,
+                    Err(_) => return RangeRequest::Full,
+                },
+                // 从start到结尾，如 100-
+                (start, "") => match start.parse::<u64>() {
+                    Ok(start) if start < total => Some(ByteRange {
+                        start,
+                        end: total - 1,
+                    }),
+                    Ok(_) => None,
+                    Err(_) => return RangeRequest::Full,
+                },
+                (start, end) => match (start.parse::<u64>(), end.parse::<u64>()) {
+                    (Ok(start), Ok(end)) if start > end => return RangeRequest::Full,
+                    (Ok(start), Ok(end)) if start < total => Some(ByteRange {
+                        start,
+                        end: end.min(total - 1),
+                    }),
+                    (Ok(_), Ok(_)) => None,
+                    _ => return RangeRequest::Full,
+                },
+            };
+            ranges.extend(range);
+        }
+        if ranges.is_empty() {
+            return RangeRequest::Unsatisfiable;
+        }
+        // 合并重叠和相邻的范围
+        ranges.sort_by_key(|r| r.start);
+        let mut merged: Vec<ByteRange> = Vec::new();
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end + 1 => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+        if merged.len() > MAX_RANGES {
+            return RangeRequest::Full;
+        }
+        RangeRequest::Partial(merged)
+    }
+}
+
+// 检查If-Range，资源没有变化时才使用Range
+pub fn if_range_matches(header: &str, validators: &Validators) -> bool {
+    let header = header.trim();
+    if header.starts_with('"') || header.starts_with("W/") {
+        // ETag必须使用强比较
+        match &validators.etag {
+            Some(etag) => !etag.starts_with("W/") && !header.starts_with("W/") && etag == header,
+            None => false,
+        }
+    } else {
+        match (validators.last_modified, parse_http_date(header)) {
+            (Some(modified), Some(date)) => {
+                let secs = |t: SystemTime| t.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+                secs(modified) == secs(date)
+            }
+            _ => false,
+        }
+    }
+}
+
+// 多个范围时生成multipart/byteranges响应体
+pub fn multipart_body(
+    contents: &[u8],
+    ranges: &[ByteRange],
+    content_type: &str,
+    boundary: &str,
+) -> Vec<u8> {
+    let total = contents.len() as u64;
+    let mut body = Vec::new();
+    for range in ranges {
+        body.extend_from_slice(&part_head(range, total, content_type, boundary));
+        body.extend_from_slice(&contents[range.start as usize..=range.end as usize]);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+    body
+}
+
+// 文件的multipart/byteranges响应体，在后台按范围分块读取，不把文件读入内存
+pub fn multipart_stream(
+    file: File,
+    total: u64,
+    ranges: Vec<ByteRange>,
+    content_type: String,
+    boundary: String,
+) -> Receiver<io::Result<Vec<u8>>> {
+    let (sender, receiver) = mpsc::channel(4);
+    tokio::spawn(async move {
+        let mut file = tokio::fs::File::from_std(file);
+        for range in &ranges {
+            let head = part_head(range, total, &content_type, &boundary);
+            if sender.send(Ok(head)).await.is_err() {
+                return;
+            }
+            if let Err(err) = file.seek(SeekFrom::Start(range.start)).await {
+                let _ = sender.send(Err(err)).await;
+                return;
+            }
+            let mut remaining = range.end - range.start + 1;
+            while remaining > 0 {
+                let mut buf = vec![0u8; remaining.min(64 * 1024) as usize];
+                // 读取失败或文件变短时发送错误，连接中止，不会发出看起来完整的响应
+                let chunk = match file.read(&mut buf).await {
+                    Ok(0) => Err(io::ErrorKind::UnexpectedEof.into()),
+                    Ok(n) => {
+                        buf.truncate(n);
+                        remaining -= n as u64;
+                        Ok(buf)
+                    }
+                    Err(err) => Err(err),
+                };
+                let failed = chunk.is_err();
+                if sender.send(chunk).await.is_err() || failed {
+                    return;
+                }
+            }
+            if sender.send(Ok(b"\r\n".to_vec())).await.is_err() {
+                return;
+            }
+        }
+        let _ = sender
+            .send(Ok(format!("--{}--\r\n", boundary).into_bytes()))
+            .await;
+    });
+    receiver
+}
+
+// 每个部分前的分隔符和头部
+fn part_head(range: &ByteRange, total: u64, content_type: &str, boundary: &str) -> Vec<u8> {
+    format!(
+        "--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+        boundary,
+        content_type,
+        range.content_range(total)
+    )
+    .into_bytes()
+}
+
+// 生成分隔符
+pub fn boundary() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    format!("byteranges_{:x}", nanos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{multipart_stream, ByteRange, RangeRequest, MAX_RANGES};
+    use std::fs;
+    use std::io::ErrorKind;
+
+    fn partial(ranges: &[(u64, u64)]) -> RangeRequest {
+        RangeRequest::Partial(
+            ranges
+                .iter()
+                .map(|&(start, end)| ByteRange { start, end })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn parse_single_range() {
+        assert_eq!(RangeRequest::parse("bytes=0-99", 1000), partial(&[(0, 99)]));
+        assert_eq!(
+            RangeRequest::parse("bytes=900-", 1000),
+            partial(&[(900, 999)])
+        );
+        // 结尾超出文件大小时截断
+        assert_eq!(
+            RangeRequest::parse("bytes=900-5000", 1000),
+            partial(&[(900, 999)])
+        );
+    }
+
+    #[test]
+    fn parse_suffix_range() {
+        assert_eq!(
+            RangeRequest::parse("bytes=-100", 1000),
+            partial(&[(900, 999)])
+        );
+        assert_eq!(
+            RangeRequest::parse("bytes=-5000", 1000),
+            partial(&[(0, 999)])
+        );
+        assert_eq!(
+            RangeRequest::parse("bytes=-0", 1000),
+            RangeRequest::Unsatisfiable
+        );
+        assert_eq!(
+            RangeRequest::parse("bytes=-10", 0),
+            RangeRequest::Unsatisfiable
+        );
+    }
+
+    #[test]
+    fn parse_malformed_range() {
+        for header in [
+            "",
+            "bytes=",
+            "bytes=,",
+            "items=0-10",
+            "bytes=abc",
+            "bytes=a-10",
+            "bytes=0-b",
+            "bytes=--10",
+            "bytes=10-5",
+            "bytes=0-10,x",
+        ] {
+            assert_eq!(
+                RangeRequest::parse(header, 1000),
+                RangeRequest::Full,
+                "{}",
+                header
+            );
+        }
+    }
+
+    #[test]
+    fn parse_overlapping_ranges() {
+        assert_eq!(
+            RangeRequest::parse("bytes=500-600, 0-99, 550-700", 1000),
+            partial(&[(0, 99), (500, 700)])
+        );
+        // 相邻的范围也会合并
+        assert_eq!(
+            RangeRequest::parse("bytes=0-9,10-19", 1000),
+            partial(&[(0, 19)])
+        );
+        assert_eq!(
+            RangeRequest::parse("bytes=0-99,-100", 1000),
+            partial(&[(0, 99), (900, 999)])
+        );
+        assert_eq!(
+            RangeRequest::parse("bytes=0-,100-200", 1000),
+            partial(&[(0, 999)])
+        );
+    }
+
+    #[test]
+    fn parse_too_many_ranges() {
+        let specs: Vec<String> = (0..=MAX_RANGES as u64)
+            .map(|i| format!("{}-{}", i * 10, i * 10))
+            .collect();
+        let header = format!("bytes={}", specs.join(","));
+        assert_eq!(RangeRequest::parse(&header, 10000), RangeRequest::Full);
+    }
+
+    #[test]
+    fn parse_unsatisfiable_range() {
+        assert_eq!(
+            RangeRequest::parse("bytes=1000-", 1000),
+            RangeRequest::Unsatisfiable
+        );
+        assert_eq!(
+            RangeRequest::parse("bytes=1000-2000", 1000),
+            RangeRequest::Unsatisfiable
+        );
+        assert_eq!(
+            RangeRequest::parse("bytes=0-10", 0),
+            RangeRequest::Unsatisfiable
+        );
+        // 只要有一个范围可以满足就返回206
+        assert_eq!(
+            RangeRequest::parse("bytes=2000-3000,0-1", 1000),
+            partial(&[(0, 1)])
+        );
+    }
+
+    #[tokio::test]
+    async fn multipart_stream_truncated_file() {
+        let path = std::env::temp_dir().join(format!("range-truncated-{}", std::process::id()));
+        fs::write(&path, b"0123456789").unwrap();
+        let file = fs::File::open(&path).unwrap();
+        // 发送过程中文件从20字节变成了10字节
+        let ranges = vec![
+            ByteRange { start: 0, end: 3 },
+            ByteRange { start: 5, end: 19 },
+        ];
+        let mut receiver = multipart_stream(
+            file,
+            20,
+            ranges,
+            String::from("text/plain"),
+            String::from("b"),
+        );
+        let mut body = Vec::new();
+        let mut error = None;
+        while let Some(chunk) = receiver.recv().await {
+            match chunk {
+                Ok(chunk) => body.extend(chunk),
+                Err(err) => error = Some(err.kind()),
+            }
+        }
+        fs::remove_file(&path).unwrap();
+        assert_eq!(error, Some(ErrorKind::UnexpectedEof));
+        let body = String::from_utf8(body).unwrap();
+        assert!(body.contains("0123\r\n"));
+        assert!(body.ends_with("56789"));
+        assert!(!body.contains("--b--"));
+    }
+}
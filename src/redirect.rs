@@ -0,0 +1,225 @@
+use crate::middleware::{Middleware, Next};
+use crate::request::HttpRequest;
+use crate::response::{HttpResponse, HttpStatus};
+use std::collections::BTreeMap;
+use std::net::Ipv6Addr;
+
+// 把明文请求重定向到HTTPS，TLS连接上的请求和豁免的路径交给后续处理
+// Unix套接字通常由本机已经处理了TLS的反向代理连接，不重定向
+#[derive(Clone, Debug)]
+pub struct HttpsRedirect {
+    // HTTPS监听的端口，443时重定向地址中省略
+    port: u16,
+    // 不重定向的路径前缀，如 /.well-known/
+    exempt: Vec<String>,
+}
+
+impl HttpsRedirect {
+    pub fn new(port: u16) -> Self {
+        Self {
+            port,
+            exempt: Vec::new(),
+        }
+    }
+
+    pub fn exempt(mut self, prefix: &str) -> Self {
+        self.exempt.push(prefix.to_string());
+        self
+    }
+
+    fn exempted(&self, path: &str) -> bool {
+        self.exempt.iter().any(|prefix| path.starts_with(prefix))
+    }
+}
+
+impl Middleware for HttpsRedirect {
+    fn handle(&self, req: HttpRequest, next: Next) -> HttpResponse<'static> {
+        if req.tls().is_some()
+            || req.listen_addr().is_some_and(|addr| addr.is_unix())
+            || self.exempted(req.original_url())
+        {
+            return next.run(req);
+        }
+        // 没有Host时无法确定HTTPS的地址，无效的Host会把客户端重定向到任意地址
+        let host = match req.host() {
+            Some(host) if !host.is_empty() => host,
+            _ => {
+                return HttpResponse::new(
+                    HttpStatus::BadRequest,
+                    None::<BTreeMap<&str, &str>>,
+                    Some("缺少Host请求头".as_bytes().to_vec()),
+                )
+            }
+        };
+        if !req
+            .headers()
+            .get("host")
+            .is_some_and(|host| valid_host(host))
+        {
+            return HttpResponse::new(
+                HttpStatus::BadRequest,
+                None::<BTreeMap<&str, &str>>,
+                Some("无效的Host请求头".as_bytes().to_vec()),
+            );
+        }
+        let mut location = if self.port == 443 {
+            format!("https://{}{}", host, req.original_url())
+        } else {
+            format!("https://{}:{}{}", host, self.port, req.original_url())
+        };
+        if !req.query().is_empty() {
+            location = format!("{}?{}", location, req.query());
+        }
+        HttpResponse::redirect(HttpStatus::PermanentRedirect, location)
+    }
+}
+
+// Host只能是 主机名[:端口] 或 [IPv6地址][:端口]，不能包含/、@和空白
+fn valid_host(host: &str) -> bool {
+    let port = match host.strip_prefix('[') {
+        Some(rest) => match rest.split_once(']') {
+            Some((ip, port)) if ip.parse::<Ipv6Addr>().is_ok() => port,
+            _ => return false,
+        },
+        None => {
+            let (name, port) = host.find(':').map_or((host, ""), |i| host.split_at(i));
+            let valid = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_');
+            if name.is_empty() || !name.chars().all(valid) {
+                return false;
+            }
+            port
+        }
+    };
+    port.is_empty()
+        || port.strip_prefix(':').is_some_and(|port| {
+            port.parse::<u16>().is_ok() && port.bytes().all(|b| b.is_ascii_digit())
+        })
+}
+
+// 在TLS连接的响应中添加Strict-Transport-Security
+#[derive(Clone, Debug)]
+pub struct Hsts {
+    // 响应头的值，如 max-age=31536000; includeSubDomains
+    value: String,
+}
+
+impl Hsts {
+    pub fn new(value: &str) -> Self {
+        Self {
+            value: value.to_string(),
+        }
+    }
+}
+
+impl Middleware for Hsts {
+    fn handle(&self, req: HttpRequest, next: Next) -> HttpResponse<'static> {
+        // 明文响应中的HSTS会被浏览器忽略，只在HTTPS上发送
+        let secure = req.tls().is_some();
+        let mut response = next.run(req);
+        if secure && response.header("Strict-Transport-Security").is_none() {
+            response.set_header("Strict-Transport-Security", self.value.clone());
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::listener::LocalAddr;
+    use crate::router::Router;
+    use std::sync::Arc;
+
+    fn send(router: &Router, url: &str, host: Option<&str>) -> HttpResponse<'static> {
+        let mut head = format!("GET {} HTTP/1.1\r\n", url);
+        if let Some(host) = host {
+            head.push_str(&format!("Host: {}\r\n", host));
+        }
+        router.handle(HttpRequest::from(&head, Vec::new(), "127.0.0.1").unwrap())
+    }
+
+    #[test]
+    fn host_format() {
+        assert!(valid_host("example.com"));
+        assert!(valid_host("example.com:8080"));
+        assert!(valid_host("my_host-1.local"));
+        assert!(valid_host("127.0.0.1:80"));
+        assert!(valid_host("[::1]"));
+        assert!(valid_host("[::1]:8080"));
+        assert!(!valid_host(""));
+        assert!(!valid_host(":8080"));
+        assert!(!valid_host("a@b"));
+        assert!(!valid_host("user@evil.com"));
+        assert!(!valid_host("a/b"));
+        assert!(!valid_host("a b"));
+        assert!(!valid_host("a\r\nLocation: x"));
+        assert!(!valid_host("example.com:"));
+        assert!(!valid_host("example.com:+80"));
+        assert!(!valid_host("example.com:65536"));
+        assert!(!valid_host("example.com:80:80"));
+        assert!(!valid_host("[::1"));
+        assert!(!valid_host("[example.com]"));
+        assert!(!valid_host("[::1]8080"));
+    }
+
+    #[test]
+    fn redirect_to_https() {
+        let router = Router::new()
+            .middleware(HttpsRedirect::new(8443).exempt("/.well-known/"))
+            .fallback(|_: &HttpRequest| {
+                HttpResponse::new(HttpStatus::Ok, None::<BTreeMap<&str, &str>>, None)
+            });
+        let response = send(&router, "/a?b=1", Some("Example.com:8080"));
+        assert_eq!(*response.status(), HttpStatus::PermanentRedirect);
+        assert_eq!(
+            response.header("Location"),
+            Some("https://example.com:8443/a?b=1")
+        );
+        let response = send(&router, "/", Some("[::1]:8080"));
+        assert_eq!(response.header("Location"), Some("https://[::1]:8443/"));
+        // 豁免的路径前缀
+        let response = send(
+            &router,
+            "/.well-known/acme-challenge/x",
+            Some("example.com"),
+        );
+        assert_eq!(*response.status(), HttpStatus::Ok);
+        let response = send(&router, "/.well-known", Some("example.com"));
+        assert_eq!(*response.status(), HttpStatus::PermanentRedirect);
+        // 缺少或无效的Host
+        assert_eq!(*send(&router, "/", None).status(), HttpStatus::BadRequest);
+        assert_eq!(
+            *send(&router, "/", Some("")).status(),
+            HttpStatus::BadRequest
+        );
+        assert_eq!(
+            *send(&router, "/", Some("a@b")).status(),
+            HttpStatus::BadRequest
+        );
+    }
+
+    #[test]
+    fn skip_unix_listener() {
+        let router =
+            Router::new()
+                .middleware(HttpsRedirect::new(8443))
+                .fallback(|_: &HttpRequest| {
+                    HttpResponse::new(HttpStatus::Ok, None::<BTreeMap<&str, &str>>, None)
+                });
+        let head = "GET / HTTP/1.1\r\nHost: example.com\r\n";
+        let mut req = HttpRequest::from(head, Vec::new(), "unix").unwrap();
+        req.insert_extension(Arc::new(LocalAddr::Unix(None)));
+        assert_eq!(*router.handle(req).status(), HttpStatus::Ok);
+        // TCP监听的请求照常重定向
+        let mut req = HttpRequest::from(head, Vec::new(), "127.0.0.1").unwrap();
+        req.insert_extension(Arc::new(LocalAddr::Tcp("127.0.0.1:80".parse().unwrap())));
+        assert_eq!(*router.handle(req).status(), HttpStatus::PermanentRedirect);
+    }
+
+    #[test]
+    fn default_https_port() {
+        let router = Router::new().middleware(HttpsRedirect::new(443));
+        let response = send(&router, "/a", Some("example.com"));
+        assert_eq!(response.header("Location"), Some("https://example.com/a"));
+    }
+}
@@ -1,15 +1,30 @@
 use crate::constant;
 use crate::error::{Fail, Result};
+use crate::listener::{LocalAddr, PeerAddr};
+use crate::proxy::ProxyInfo;
+use crate::tls::{ClientCert, TlsInfo};
 use crate::utils::split;
+use bytes::Bytes;
+use std::any::{Any, TypeId};
+use std::borrow::Cow;
 use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+// 连接上的每个请求都会插入的扩展，如TLS信息和监听地址
+pub type Extensions = Vec<Arc<dyn Any + Send + Sync>>;
 
 // 支持的http方法
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum HttpMethod {
     Unknown,
     Options,
+    Head,
     Get,
     Post,
+    Put,
+    Patch,
+    Delete,
 }
 
 // 实现字符串的into()方法
@@ -17,13 +32,32 @@ impl From<&str> for HttpMethod {
     fn from(s: &str) -> Self {
         match s {
             "OPTIONS" => HttpMethod::Options,
+            "HEAD" => HttpMethod::Head,
             "GET" => HttpMethod::Get,
             "POST" => HttpMethod::Post,
+            "PUT" => HttpMethod::Put,
+            "PATCH" => HttpMethod::Patch,
+            "DELETE" => HttpMethod::Delete,
             _ => HttpMethod::Unknown,
         }
     }
 }
 
+impl HttpMethod {
+    pub fn as_str(&self) -> &str {
+        match self {
+            HttpMethod::Unknown => "UNKNOWN",
+            HttpMethod::Options => "OPTIONS",
+            HttpMethod::Head => "HEAD",
+            HttpMethod::Get => "GET",
+            HttpMethod::Post => "POST",
+            HttpMethod::Put => "PUT",
+            HttpMethod::Patch => "PATCH",
+            HttpMethod::Delete => "DELETE",
+        }
+    }
+}
+
 // 支持的http版本
 #[derive(Debug, PartialEq)]
 pub enum HttpVersion {
@@ -49,18 +83,28 @@ impl From<&str> for HttpVersion {
 pub struct HttpRequest<'a> {
     // 请求方法
     method: HttpMethod,
-    // URL
-    url: &'a str,
+    // URL（嵌套路由中为剥离前缀后的路径，重写规则可能会修改）
+    url: Cow<'a, str>,
+    // 原始URL
+    original_url: &'a str,
     // 请求版本
     version: HttpVersion,
     // 源ip
     ip: &'a str,
     // 请求头
     headers: BTreeMap<String, &'a str>,
+    // 原始查询字符串，重写规则可能会修改
+    query: Cow<'a, str>,
     // 参数
-    search_params: BTreeMap<String, &'a str>,
-    // 请求体
+    search_params: BTreeMap<String, Cow<'a, str>>,
+    // 路径参数
+    params: BTreeMap<String, String>,
+    // 表单请求体的字段
     _body: BTreeMap<String, Vec<u8>>,
+    // 原始请求体，Form和Json从这里解析
+    raw_body: Bytes,
+    // 扩展数据，如路由共享的状态
+    extensions: BTreeMap<TypeId, Arc<dyn Any + Send + Sync>>,
 }
 
 #[allow(dead_code)]
@@ -106,17 +150,23 @@ impl<'a> HttpRequest<'a> {
         }
 
         // 查询参数
-        let search_params = parse_parameters(search_params_raw, |v| v)?;
+        let search_params = lowercase_keys(parse_parameters(search_params_raw, Cow::Borrowed)?);
         // 处理请求体
         let body = parse_body(&headers, &raw_body)?;
+        let raw_body = Bytes::from(raw_body);
         Ok(Self {
             method,
-            url,
+            url: Cow::Borrowed(url),
+            original_url: url,
             version,
             ip,
             headers,
+            query: Cow::Borrowed(search_params_raw),
             search_params,
+            params: BTreeMap::new(),
             _body: body,
+            raw_body,
+            extensions: BTreeMap::new(),
         })
     }
 
@@ -126,21 +176,101 @@ impl<'a> HttpRequest<'a> {
     pub fn url(&self) -> &str {
         &self.url
     }
+    pub fn set_url<U: Into<Cow<'a, str>>>(&mut self, url: U) {
+        self.url = url.into();
+    }
+    pub fn original_url(&self) -> &'a str {
+        self.original_url
+    }
     pub fn version(&self) -> &HttpVersion {
         &self.version
     }
+    // 客户端IP，Unix套接字的连接为"unix"
     pub fn ip(&self) -> &str {
-        &self.ip
+        self.ip
     }
     pub fn headers(&self) -> &BTreeMap<String, &'a str> {
         &self.headers
     }
-    pub fn search_params(&self) -> &BTreeMap<String, &'a str> {
+    // 不含端口的小写主机名
+    pub fn host(&self) -> Option<String> {
+        let host = self.headers.get("host")?.trim();
+        let name = if host.starts_with('[') {
+            // IPv6地址，如 [::1]:8080
+            host.split_inclusive(']').next().unwrap_or(host)
+        } else {
+            host.split(':').next().unwrap_or(host)
+        };
+        Some(name.to_lowercase())
+    }
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+    // 修改查询字符串并重新解析参数
+    pub fn set_query<Q: Into<Cow<'a, str>>>(&mut self, query: Q) -> Result<()> {
+        let query = query.into();
+        self.search_params = lowercase_keys(match &query {
+            Cow::Borrowed(query) => parse_parameters(query, Cow::Borrowed)?,
+            Cow::Owned(query) => parse_parameters(query, |v| Cow::Owned(v.to_string()))?,
+        });
+        self.query = query;
+        Ok(())
+    }
+    pub fn search_params(&self) -> &BTreeMap<String, Cow<'a, str>> {
         &self.search_params
     }
+    pub fn params(&self) -> &BTreeMap<String, String> {
+        &self.params
+    }
+    pub fn params_mut(&mut self) -> &mut BTreeMap<String, String> {
+        &mut self.params
+    }
     pub fn body(&self) -> &BTreeMap<String, Vec<u8>> {
         &self._body
     }
+    pub fn raw_body(&self) -> &Bytes {
+        &self.raw_body
+    }
+    // 保留参数名大小写的查询参数，值没有解码
+    pub fn query_pairs(&self) -> Result<BTreeMap<String, &str>> {
+        parse_parameters(&self.query, |v| v)
+    }
+    // 保留字段名大小写的表单，请求体不是表单时为空
+    pub fn form_fields(&self) -> Result<BTreeMap<String, Vec<u8>>> {
+        parse_form(&self.headers, &self.raw_body).map(Option::unwrap_or_default)
+    }
+    pub fn extension<T: 'static>(&self) -> Option<&T> {
+        self.extensions
+            .get(&TypeId::of::<T>())
+            .and_then(|e| e.downcast_ref::<T>())
+    }
+    // TLS连接的信息，普通HTTP连接时为None
+    pub fn tls(&self) -> Option<&TlsInfo> {
+        self.extension::<TlsInfo>()
+    }
+    // 通过验证的客户端证书
+    pub fn client_cert(&self) -> Option<&ClientCert> {
+        self.tls()?.client.as_ref()
+    }
+    // 接受连接的监听套接字的本地地址
+    pub fn listen_addr(&self) -> Option<&LocalAddr> {
+        self.extension::<LocalAddr>()
+    }
+    // 客户端地址，经过PROXY协议时是代理报告的地址
+    pub fn peer_addr(&self) -> Option<&PeerAddr> {
+        self.extension::<PeerAddr>()
+    }
+    // 客户端IP和端口，Unix套接字的连接为None
+    pub fn client_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr()?.socket_addr()
+    }
+    // PROXY协议传递的连接信息，直接连接时为None
+    pub fn proxy(&self) -> Option<&ProxyInfo> {
+        self.extension::<ProxyInfo>()
+    }
+    pub fn insert_extension(&mut self, extension: Arc<dyn Any + Send + Sync>) {
+        self.extensions.insert((*extension).type_id(), extension);
+    }
     pub fn body_utf8(&self) -> BTreeMap<String, String> {
         let mut form = BTreeMap::new();
         for (k, v) in &self._body {
@@ -150,8 +280,18 @@ impl<'a> HttpRequest<'a> {
     }
 }
 
-// 处理请求体
+// 处理请求体，表单字段名转为小写，其他类型的请求体通过raw_body读取
 fn parse_body(headers: &BTreeMap<String, &str>, body: &[u8]) -> Result<BTreeMap<String, Vec<u8>>> {
+    Ok(parse_form(headers, body)?
+        .map(lowercase_keys)
+        .unwrap_or_default())
+}
+
+// 解析表单，保留字段名的大小写，不是表单时返回None
+fn parse_form(
+    headers: &BTreeMap<String, &str>,
+    body: &[u8],
+) -> Result<Option<BTreeMap<String, Vec<u8>>>> {
     let mut boundary = None;
     // 获取content-type
     let content_type = match headers.get("content-type") {
@@ -172,17 +312,16 @@ fn parse_body(headers: &BTreeMap<String, &str>, body: &[u8]) -> Result<BTreeMap<
         parse_parameters(&String::from_utf8(body.to_vec())?, |v| {
             v.as_bytes().to_vec()
         })
+        .map(Some)
     } else if content_type.starts_with(constant::MULTIPART_FORM_DATA) {
         // Multipart表单
         parse_multipart_form(
             body,
             boundary.ok_or_else(|| Fail::new("没有有效的boundary"))?,
         )
+        .map(Some)
     } else {
-        // 其他类型存储为原始字节
-        let mut map = BTreeMap::new();
-        map.insert(String::from("__raw"), body.to_vec());
-        Ok(map)
+        Ok(None)
     }
 }
 
@@ -213,7 +352,7 @@ fn parse_multipart_form(body: &[u8], boundary: &str) -> Result<BTreeMap<String,
                 name = line_str.split(';').map(|s| s.trim()).find_map(|s| {
                     if s.starts_with("name=") {
                         let name = s.split('=').nth(1)?;
-                        Some(name[1..(name.len() - 1)].to_lowercase())
+                        Some(name[1..(name.len() - 1)].to_string())
                     } else {
                         None
                     }
@@ -238,7 +377,15 @@ fn parse_multipart_form(body: &[u8], boundary: &str) -> Result<BTreeMap<String,
     Ok(params)
 }
 
-// 转换表单和查询参数
+// 参数名转为小写，用于HttpRequest::search_params和body
+fn lowercase_keys<V>(params: BTreeMap<String, V>) -> BTreeMap<String, V> {
+    params
+        .into_iter()
+        .map(|(key, value)| (key.to_lowercase(), value))
+        .collect()
+}
+
+// 转换表单和查询参数，保留参数名的大小写
 fn parse_parameters<'a, V>(
     raw: &'a str,
     process_value: fn(&'a str) -> V,
@@ -253,7 +400,7 @@ fn parse_parameters<'a, V>(
             ps.next()
                 .ok_or_else(|| Fail::new("损坏的参数"))?
                 .trim()
-                .to_lowercase(),
+                .to_string(),
             process_value(if let Some(value) = ps.next() {
                 value.trim()
             } else {
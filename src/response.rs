@@ -1,36 +1,169 @@
+use crate::connection::Connection;
 use crate::constant;
+use bytes::Bytes;
 use std::borrow::Cow;
 use std::collections::BTreeMap;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::fs::File;
+use std::future::Future;
+use std::io::{self, SeekFrom};
+use std::pin::Pin;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::sync::mpsc::{self, Receiver};
 
 // HTTP状态码
 #[allow(dead_code)]
 #[derive(Debug, PartialEq, Clone)]
 pub enum HttpStatus {
+    SwitchingProtocols,
     Ok,
+    PartialContent,
+    MovedPermanently,
+    Found,
+    TemporaryRedirect,
+    NotModified,
+    PermanentRedirect,
     BadRequest,
     NotFound,
+    MethodNotAllowed,
+    PreconditionFailed,
+    RangeNotSatisfiable,
     InternalServerError,
+    HttpVersionNotSupported,
 }
 
 // 实现 HttpStatus 的字符串表示方法
 impl HttpStatus {
     fn to_str(&self) -> &str {
         match self {
+            HttpStatus::SwitchingProtocols => "101 Switching Protocols",
             HttpStatus::Ok => "200 OK",
+            HttpStatus::PartialContent => "206 Partial Content",
+            HttpStatus::MovedPermanently => "301 Moved Permanently",
+            HttpStatus::Found => "302 Found",
+            HttpStatus::NotModified => "304 Not Modified",
+            HttpStatus::TemporaryRedirect => "307 Temporary Redirect",
+            HttpStatus::PermanentRedirect => "308 Permanent Redirect",
             HttpStatus::BadRequest => "400 Bad Request",
             HttpStatus::NotFound => "404 Not Found",
+            HttpStatus::MethodNotAllowed => "405 Method Not Allowed",
+            HttpStatus::PreconditionFailed => "412 Precondition Failed",
+            HttpStatus::RangeNotSatisfiable => "416 Range Not Satisfiable",
             HttpStatus::InternalServerError => "500 Internal Server Error",
+            HttpStatus::HttpVersionNotSupported => "505 HTTP Version Not Supported",
         }
     }
+
+    // 数字状态码
+    pub fn code(&self) -> u16 {
+        self.to_str()[..3].parse().unwrap_or(500)
+    }
+}
+
+// 响应体
+pub enum Body {
+    // 完整的响应体，使用Content-Length，缓存的内容可以共享不复制
+    Full(Bytes),
+    // 流式响应体，使用chunked编码发送，发送端关闭时结束。
+    // 收到错误时中止连接，不发送结束标志，客户端能发现内容不完整
+    #[allow(dead_code)]
+    Stream(Receiver<io::Result<Vec<u8>>>),
+    // 文件中从offset开始的len个字节，发送时不读入内存
+    File {
+        file: File,
+        offset: u64,
+        len: u64,
+    },
+}
+
+impl Body {
+    // 转换为流式响应体，文件在后台分块读取
+    pub fn into_stream(self) -> Receiver<io::Result<Vec<u8>>> {
+        let (sender, receiver) = mpsc::channel(4);
+        match self {
+            Body::Full(bytes) => {
+                let _ = sender.try_send(Ok(bytes.to_vec()));
+            }
+            Body::Stream(receiver) => return receiver,
+            Body::File { file, offset, len } => {
+                tokio::spawn(async move {
+                    let mut file = tokio::fs::File::from_std(file);
+                    if let Err(err) = file.seek(SeekFrom::Start(offset)).await {
+                        let _ = sender.send(Err(err)).await;
+                        return;
+                    }
+                    let mut remaining = len;
+                    while remaining > 0 {
+                        let mut buf = vec![0u8; remaining.min(64 * 1024) as usize];
+                        let chunk = match file.read(&mut buf).await {
+                            // 文件变短
+                            Ok(0) => Err(io::ErrorKind::UnexpectedEof.into()),
+                            Ok(n) => {
+                                buf.truncate(n);
+                                remaining -= n as u64;
+                                Ok(buf)
+                            }
+                            Err(err) => Err(err),
+                        };
+                        let failed = chunk.is_err();
+                        if sender.send(chunk).await.is_err() || failed {
+                            return;
+                        }
+                    }
+                });
+            }
+        }
+        receiver
+    }
+}
+
+impl Debug for Body {
+    fn fmt(&self, formatter: &mut Formatter) -> FmtResult {
+        match self {
+            Body::Full(bytes) => write!(formatter, "Full({} bytes)", bytes.len()),
+            Body::Stream(_) => write!(formatter, "Stream"),
+            Body::File { offset, len, .. } => {
+                write!(formatter, "File({} bytes at {})", len, offset)
+            }
+        }
+    }
+}
+
+type UpgradeFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+// 协议升级，101响应发送后接管连接，参数是连接和已经读取但还没有处理的数据
+pub struct Upgrade(Box<dyn FnOnce(Connection, Vec<u8>) -> UpgradeFuture + Send>);
+
+impl Upgrade {
+    pub fn new<F, Fut>(callback: F) -> Self
+    where
+        F: FnOnce(Connection, Vec<u8>) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        Self(Box::new(move |stream, buffer| {
+            Box::pin(callback(stream, buffer))
+        }))
+    }
+
+    pub async fn run(self, stream: Connection, buffer: Vec<u8>) {
+        (self.0)(stream, buffer).await
+    }
+}
+
+impl Debug for Upgrade {
+    fn fmt(&self, formatter: &mut Formatter) -> FmtResult {
+        write!(formatter, "Upgrade")
+    }
 }
 
 // HTTP响应
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug)]
 pub struct HttpResponse<'a> {
     version: Cow<'a, str>,
     status: HttpStatus,
     headers: BTreeMap<Cow<'a, str>, Cow<'a, str>>,
-    body: Option<Vec<u8>>,
+    body: Option<Body>,
+    upgrade: Option<Upgrade>,
 }
 
 impl<'a> Default for HttpResponse<'a> {
@@ -40,6 +173,7 @@ impl<'a> Default for HttpResponse<'a> {
             status: HttpStatus::Ok,
             headers: BTreeMap::new(),
             body: None,
+            upgrade: None,
         };
         response.headers.insert(
             Cow::Borrowed("Content-Type"),
@@ -62,28 +196,110 @@ impl<'a> HttpResponse<'a> {
     where
         S: Into<Cow<'a, str>>,
     {
-        let mut response: HttpResponse<'a> = HttpResponse::default();
-        response.status = status;
+        let mut response: HttpResponse<'a> = HttpResponse {
+            status,
+            body: body.map(|body| Body::Full(body.into())),
+            ..HttpResponse::default()
+        };
         if let Some(hs) = headers {
             for (k, v) in hs {
                 response.headers.insert(k.into(), v.into());
             }
         }
-        response.body = body;
         response
     }
 
     pub fn not_found(body: Option<Vec<u8>>) -> HttpResponse<'a> {
-        let mut response: HttpResponse<'a> = HttpResponse::default();
-        response.status = HttpStatus::NotFound;
+        let mut response: HttpResponse<'a> = HttpResponse {
+            status: HttpStatus::NotFound,
+            body: body.map(|body| Body::Full(body.into())),
+            ..HttpResponse::default()
+        };
         response.headers.insert(
             Cow::Borrowed("Content-Type"),
             Cow::Borrowed(constant::TEXT_HTML),
         );
-        response.body = body;
         response
     }
 
+    // 流式响应，每次从receiver收到的数据作为一个chunk发送
+    #[allow(dead_code)]
+    pub fn stream<S>(
+        status: HttpStatus,
+        headers: Option<BTreeMap<S, S>>,
+        receiver: Receiver<io::Result<Vec<u8>>>,
+    ) -> HttpResponse<'a>
+    where
+        S: Into<Cow<'a, str>>,
+    {
+        let mut response = HttpResponse::new(status, headers, None);
+        response.body = Some(Body::Stream(receiver));
+        response
+    }
+
+    // 重定向
+    pub fn redirect<L>(status: HttpStatus, location: L) -> HttpResponse<'a>
+    where
+        L: Into<Cow<'a, str>>,
+    {
+        let mut response: HttpResponse<'a> = HttpResponse {
+            status,
+            ..HttpResponse::default()
+        };
+        response.set_header("Location", location);
+        response
+    }
+
+    // 设置响应头
+    pub fn set_header<K, V>(&mut self, key: K, value: V)
+    where
+        K: Into<Cow<'a, str>>,
+        V: Into<Cow<'a, str>>,
+    {
+        self.headers.insert(key.into(), value.into());
+    }
+
+    pub fn status(&self) -> &HttpStatus {
+        &self.status
+    }
+
+    // 忽略大小写获取响应头
+    pub fn header(&self, key: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_ref())
+    }
+
+    pub fn header_pairs(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.headers.iter().map(|(k, v)| (k.as_ref(), v.as_ref()))
+    }
+
+    pub fn remove_header(&mut self, key: &str) {
+        self.headers.retain(|k, _| !k.eq_ignore_ascii_case(key));
+    }
+
+    pub fn body(&self) -> Option<&Body> {
+        self.body.as_ref()
+    }
+
+    pub fn take_body(&mut self) -> Option<Body> {
+        self.body.take()
+    }
+
+    pub fn set_body(&mut self, body: Option<Body>) {
+        self.body = body;
+    }
+
+    // 101响应发送后执行的协议升级
+    pub fn set_upgrade(&mut self, upgrade: Upgrade) {
+        self.upgrade = Some(upgrade);
+    }
+
+    pub fn take_upgrade(&mut self) -> Option<Upgrade> {
+        self.upgrade.take()
+    }
+
     fn headers(&self) -> String {
         let mut header_string = String::new();
         for (k, v) in &self.headers {
@@ -92,22 +308,38 @@ impl<'a> HttpResponse<'a> {
         header_string
     }
 
-    // 转换为字节数组
-    pub fn to_vec(&self) -> Vec<u8> {
-        let mut vec = format!(
-            "{} {}\r\n{}Content-Length: {}\r\n\r\n",
+    // 状态行和响应头
+    pub fn head(&self) -> Vec<u8> {
+        let framing = match &self.body {
+            // 101和304响应没有响应体，也不发送Content-Length
+            _ if matches!(
+                self.status,
+                HttpStatus::SwitchingProtocols | HttpStatus::NotModified
+            ) =>
+            {
+                String::new()
+            }
+            Some(Body::Stream(_)) => String::from("Transfer-Encoding: chunked\r\n"),
+            Some(Body::Full(b)) => format!("Content-Length: {}\r\n", b.len()),
+            Some(Body::File { len, .. }) => format!("Content-Length: {}\r\n", len),
+            None => String::from("Content-Length: 0\r\n"),
+        };
+        format!(
+            "{} {}\r\n{}{}\r\n",
             &self.version,
             &self.status.to_str(),
             &self.headers(),
-            self.body.as_ref().map_or(0, |b| b.len()),
+            framing,
         )
-        .as_bytes()
-        .to_vec();
+        .into_bytes()
+    }
 
-        if let Some(b) = &self.body {
+    // 转换为字节数组，流式响应体需要单独发送
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut vec = self.head();
+        if let Some(Body::Full(b)) = &self.body {
             vec.extend_from_slice(b);
         }
-
         vec
     }
 }
@@ -0,0 +1,363 @@
+use crate::error::{Fail, Result};
+use crate::middleware::{Middleware, Next};
+use crate::request::{HttpMethod, HttpRequest};
+use crate::response::{HttpResponse, HttpStatus};
+use crate::vhost::HostPattern;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+// 路径匹配方式
+pub enum PathMatch {
+    // 完全匹配
+    Exact(String),
+    // 前缀匹配，目标地址替换掉匹配的前缀
+    Prefix(String),
+    // 正则匹配，目标地址中可以用 $1、${name} 引用捕获组。
+    // 匹配到的部分替换为整个目标地址，所以需要匹配整个路径，用 PathMatch::regex 创建时会加上 ^ 和 $
+    Regex(Regex),
+}
+
+impl PathMatch {
+    // 匹配整个路径的正则
+    pub fn regex(pattern: &str) -> Result<Self> {
+        Ok(PathMatch::Regex(Regex::new(&format!("^(?:{})$", pattern))?))
+    }
+
+    // 匹配成功时返回替换后的地址
+    fn apply(&self, url: &str, target: &str) -> Option<String> {
+        match self {
+            PathMatch::Exact(path) => (url == path).then(|| target.to_string()),
+            // 前缀之后必须是/、?或结尾，/old不匹配/older
+            PathMatch::Prefix(prefix) => url
+                .strip_prefix(prefix.as_str())
+                .filter(|rest| {
+                    prefix.ends_with('/')
+                        || rest.is_empty()
+                        || rest.starts_with('/')
+                        || rest.starts_with('?')
+                })
+                .map(|rest| format!("{}{}", target, rest)),
+            PathMatch::Regex(regex) => regex.captures(url).map(|caps| {
+                let mut replaced = String::new();
+                caps.expand(target, &mut replaced);
+                replaced
+            }),
+        }
+    }
+}
+
+// 匹配后的动作
+pub enum RuleAction {
+    // 内部重写，客户端不可见
+    Rewrite(String),
+    // 重定向，状态码为301、302、307或308
+    Redirect(HttpStatus, String),
+}
+
+pub struct Rule {
+    path: PathMatch,
+    action: RuleAction,
+    // 限定的请求方法，为空时不限
+    methods: Vec<HttpMethod>,
+    // 限定的主机名
+    host: Option<HostPattern>,
+}
+
+#[allow(dead_code)]
+impl Rule {
+    pub fn new(path: PathMatch, action: RuleAction) -> Self {
+        Self {
+            path,
+            action,
+            methods: Vec::new(),
+            host: None,
+        }
+    }
+
+    pub fn method(mut self, method: HttpMethod) -> Self {
+        self.methods.push(method);
+        self
+    }
+
+    pub fn host(mut self, pattern: &str) -> Self {
+        self.host = Some(HostPattern::parse(pattern));
+        self
+    }
+
+    fn condition(&self, req: &HttpRequest) -> bool {
+        if !self.methods.is_empty() && !self.methods.contains(req.method()) {
+            return false;
+        }
+        match &self.host {
+            Some(pattern) => req.host().is_some_and(|host| pattern.matches(&host)),
+            None => true,
+        }
+    }
+}
+
+// 重写和重定向规则，作为中间件放在路由前面，按顺序执行：
+// 重写会修改请求地址并继续匹配后面的规则，重定向会直接返回
+#[derive(Default)]
+pub struct RewriteRules {
+    rules: Vec<Rule>,
+}
+
+#[allow(dead_code)]
+impl RewriteRules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn rule(mut self, rule: Rule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    // 从配置文件加载规则
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    // 每行一条规则，#开头为注释，正则需要匹配整个路径：
+    // rewrite <exact|prefix|regex> <匹配> <目标> [method=GET,POST] [host=*.example.com]
+    // redirect <301|302|307|308> <exact|prefix|regex> <匹配> <目标> [method=...] [host=...]
+    pub fn parse(config: &str) -> Result<Self> {
+        let mut rules = Self::new();
+        for (i, line) in config.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let rule = parse_rule(line).map_err(|e| Fail::new(format!("第{}行: {}", i + 1, e)))?;
+            rules.rules.push(rule);
+        }
+        Ok(rules)
+    }
+}
+
+fn parse_rule(line: &str) -> Result<Rule> {
+    let mut words = line.split_whitespace();
+    let mut next = |name: &str| {
+        words
+            .next()
+            .ok_or_else(|| Fail::new(format!("缺少{}", name)))
+    };
+    let kind = next("动作")?;
+    let status = match kind {
+        "rewrite" => None,
+        "redirect" => Some(match next("状态码")? {
+            "301" => HttpStatus::MovedPermanently,
+            "302" => HttpStatus::Found,
+            "307" => HttpStatus::TemporaryRedirect,
+            "308" => HttpStatus::PermanentRedirect,
+            code => return Fail::from(format!("不支持的重定向状态码: {}", code)),
+        }),
+        _ => return Fail::from(format!("未知的动作: {}", kind)),
+    };
+    let match_kind = next("匹配方式")?;
+    let pattern = next("匹配内容")?;
+    let path = match match_kind {
+        "exact" => PathMatch::Exact(pattern.to_string()),
+        "prefix" => PathMatch::Prefix(pattern.to_string()),
+        "regex" => PathMatch::regex(pattern)?,
+        _ => return Fail::from(format!("未知的匹配方式: {}", match_kind)),
+    };
+    let target = next("目标地址")?.to_string();
+    let action = match status {
+        Some(status) => RuleAction::Redirect(status, target),
+        None => RuleAction::Rewrite(target),
+    };
+    let mut rule = Rule::new(path, action);
+    for condition in words {
+        match condition.split_once('=') {
+            Some(("method", methods)) => {
+                for method in methods.split(',') {
+                    match HttpMethod::from(method.to_uppercase().as_str()) {
+                        HttpMethod::Unknown => {
+                            return Fail::from(format!("未知的请求方法: {}", method))
+                        }
+                        method => rule.methods.push(method),
+                    }
+                }
+            }
+            Some(("host", host)) => rule.host = Some(HostPattern::parse(host)),
+            _ => return Fail::from(format!("未知的条件: {}", condition)),
+        }
+    }
+    Ok(rule)
+}
+
+impl Middleware for RewriteRules {
+    fn handle(&self, mut req: HttpRequest, next: Next) -> HttpResponse<'static> {
+        for rule in &self.rules {
+            if !rule.condition(&req) {
+                continue;
+            }
+            match &rule.action {
+                RuleAction::Rewrite(target) => {
+                    if let Some(url) = rule.path.apply(req.url(), target) {
+                        // 目标地址带查询参数时替换原来的查询参数
+                        match url.split_once('?') {
+                            Some((path, query)) => {
+                                let query = query.to_string();
+                                req.set_url(path.to_string());
+                                if req.set_query(query).is_err() {
+                                    return HttpResponse::new(
+                                        HttpStatus::BadRequest,
+                                        None::<BTreeMap<&str, &str>>,
+                                        None,
+                                    );
+                                }
+                            }
+                            None => req.set_url(url),
+                        }
+                    }
+                }
+                RuleAction::Redirect(status, target) => {
+                    if let Some(mut location) = rule.path.apply(req.url(), target) {
+                        // 目标地址没有查询参数时保留原来的查询参数
+                        if !req.query().is_empty() && !location.contains('?') {
+                            location = format!("{}?{}", location, req.query());
+                        }
+                        return HttpResponse::redirect(status.clone(), location);
+                    }
+                }
+            }
+        }
+        next.run(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::Body;
+    use crate::router::Router;
+
+    // 响应体为处理器看到的路径和查询参数
+    fn router(config: &str) -> Router {
+        Router::new()
+            .middleware(RewriteRules::parse(config).unwrap())
+            .fallback(|req: &HttpRequest| {
+                let body = format!("{}?{}", req.url(), req.query());
+                HttpResponse::new(
+                    HttpStatus::Ok,
+                    None::<BTreeMap<&str, &str>>,
+                    Some(body.into_bytes()),
+                )
+            })
+    }
+
+    fn send(router: &Router, method: &str, url: &str, host: &str) -> HttpResponse<'static> {
+        let head = format!("{} {} HTTP/1.1\r\nHost: {}\r\n", method, url, host);
+        router.handle(HttpRequest::from(&head, Vec::new(), "127.0.0.1").unwrap())
+    }
+
+    fn body(response: &HttpResponse) -> String {
+        match response.body() {
+            Some(Body::Full(bytes)) => String::from_utf8_lossy(bytes).to_string(),
+            _ => String::new(),
+        }
+    }
+
+    fn parse_error(config: &str) -> String {
+        match RewriteRules::parse(config) {
+            Ok(_) => String::new(),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn parse_rules() {
+        let rules = RewriteRules::parse(
+            "# 注释\n\nrewrite exact /a /b\nredirect 308 prefix /old /new method=get,head host=*.example.com\n",
+        )
+        .unwrap();
+        assert_eq!(rules.rules.len(), 2);
+        let rule = &rules.rules[1];
+        assert_eq!(rule.methods, vec![HttpMethod::Get, HttpMethod::Head]);
+        assert!(matches!(rule.host, Some(HostPattern::Wildcard(_))));
+        assert!(matches!(
+            rule.action,
+            RuleAction::Redirect(HttpStatus::PermanentRedirect, _)
+        ));
+    }
+
+    #[test]
+    fn parse_bad_lines() {
+        assert!(parse_error("rewrite exact /a /b\nmove exact /a /b").contains("第2行"));
+        assert!(parse_error("rewrite exact /a").contains("缺少目标地址"));
+        assert!(parse_error("rewrite glob /a /b").contains("未知的匹配方式"));
+        assert!(parse_error("redirect 303 exact /a /b").contains("不支持的重定向状态码"));
+        assert!(parse_error("rewrite regex /a( /b").contains("第1行"));
+        assert!(parse_error("rewrite exact /a /b method=FETCH").contains("未知的请求方法"));
+        assert!(parse_error("rewrite exact /a /b port=80").contains("未知的条件"));
+    }
+
+    #[test]
+    fn match_kinds() {
+        let router = router(
+            "rewrite exact /a /exact\n\
+             rewrite prefix /docs /manual\n\
+             rewrite regex /users/(\\d+) /profile?id=$1\n",
+        );
+        assert_eq!(body(&send(&router, "GET", "/a", "h")), "/exact?");
+        assert_eq!(body(&send(&router, "GET", "/a/b", "h")), "/a/b?");
+        assert_eq!(body(&send(&router, "GET", "/docs/x", "h")), "/manual/x?");
+        assert_eq!(body(&send(&router, "GET", "/docs", "h")), "/manual?");
+        // /docs不匹配/docsx
+        assert_eq!(body(&send(&router, "GET", "/docsx", "h")), "/docsx?");
+        assert_eq!(
+            body(&send(&router, "GET", "/users/42", "h")),
+            "/profile?id=42"
+        );
+        // 正则需要匹配整个路径
+        assert_eq!(
+            body(&send(&router, "GET", "/users/42/x", "h")),
+            "/users/42/x?"
+        );
+        assert_eq!(
+            body(&send(&router, "GET", "/v/users/42", "h")),
+            "/v/users/42?"
+        );
+    }
+
+    #[test]
+    fn conditions() {
+        let router = router(
+            "rewrite exact /a /post method=POST\n\
+             rewrite exact /a /host host=*.example.com\n",
+        );
+        assert_eq!(body(&send(&router, "POST", "/a", "h")), "/post?");
+        assert_eq!(
+            body(&send(&router, "GET", "/a", "www.example.com:80")),
+            "/host?"
+        );
+        assert_eq!(body(&send(&router, "GET", "/a", "example.org")), "/a?");
+    }
+
+    #[test]
+    fn rewrite_and_redirect() {
+        let router = router(
+            "rewrite prefix /old /new\n\
+             redirect 301 exact /new/a /moved\n\
+             redirect 302 exact /b /found?x=1\n\
+             redirect 307 exact /c /temporary\n",
+        );
+        // 重写后继续匹配后面的规则
+        let response = send(&router, "GET", "/old/a?q=1", "h");
+        assert_eq!(*response.status(), HttpStatus::MovedPermanently);
+        assert_eq!(response.header("Location"), Some("/moved?q=1"));
+        // 目标地址有查询参数时不保留原来的
+        let response = send(&router, "GET", "/b?q=1", "h");
+        assert_eq!(*response.status(), HttpStatus::Found);
+        assert_eq!(response.header("Location"), Some("/found?x=1"));
+        let response = send(&router, "GET", "/c", "h");
+        assert_eq!(*response.status(), HttpStatus::TemporaryRedirect);
+        let response = send(&router, "GET", "/old/b", "h");
+        assert_eq!(*response.status(), HttpStatus::Ok);
+        assert_eq!(body(&response), "/new/b?");
+    }
+}
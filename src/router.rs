@@ -1,11 +1,339 @@
-use super::handler::{Handler, StaticHandler};
-use crate::request::HttpRequest;
-use crate::response::HttpResponse;
+use crate::handler::Handler;
+use crate::middleware::{Middleware, Next};
+use crate::request::{HttpMethod, HttpRequest};
+use crate::response::{HttpResponse, HttpStatus};
+use crate::utils::url_decode;
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::sync::Arc;
 
-pub struct Router;
+// 路径片段
+enum Segment {
+    // 固定路径
+    Static(String),
+    // 路径参数，如 /users/:id
+    Param(String),
+    // 通配剩余路径，如 /files/*path
+    Wildcard(String),
+}
+
+struct Route {
+    method: HttpMethod,
+    segments: Vec<Segment>,
+    handler: Arc<dyn Handler>,
+}
+
+impl Route {
+    // 匹配成功时返回路径参数
+    fn matches(&self, url: &str) -> Option<BTreeMap<String, String>> {
+        let mut params = BTreeMap::new();
+        let mut parts = url.split('/').filter(|p| !p.is_empty());
+        for segment in &self.segments {
+            match segment {
+                Segment::Static(s) => {
+                    if parts.next()? != s {
+                        return None;
+                    }
+                }
+                Segment::Param(name) => {
+                    let part = parts.next()?;
+                    params.insert(name.clone(), url_decode(part, false));
+                }
+                Segment::Wildcard(name) => {
+                    // 逐个片段解码后再拼接
+                    let rest: Vec<String> = parts.by_ref().map(|p| url_decode(p, false)).collect();
+                    params.insert(name.clone(), rest.join("/"));
+                }
+            }
+        }
+        // 还有未匹配的片段
+        match parts.next() {
+            Some(_) => None,
+            None => Some(params),
+        }
+    }
+}
+
+// 挂载在某个前缀下的子路由
+struct Mount {
+    prefix: String,
+    router: Arc<Router>,
+}
 
+impl Mount {
+    // 匹配成功时返回剥离前缀后的路径
+    fn strip<'a>(&self, url: &'a str) -> Option<&'a str> {
+        let rest = url.strip_prefix(self.prefix.as_str())?;
+        if rest.is_empty() {
+            Some("/")
+        } else if rest.starts_with('/') {
+            Some(rest)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+    mounts: Vec<Mount>,
+    middlewares: Vec<Arc<dyn Middleware>>,
+    fallback: Option<Arc<dyn Handler>>,
+    states: Vec<Arc<dyn Any + Send + Sync>>,
+}
+
+#[allow(dead_code)]
 impl Router {
-    pub fn route<'a>(req: HttpRequest) -> HttpResponse<'a> {
-        StaticHandler::handle(&req)
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 注册路由
+    pub fn route<H>(mut self, method: HttpMethod, path: &str, handler: H) -> Self
+    where
+        H: Handler + 'static,
+    {
+        let segments = path
+            .trim_matches('/')
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                if let Some(name) = s.strip_prefix(':') {
+                    Segment::Param(name.to_string())
+                } else if let Some(name) = s.strip_prefix('*') {
+                    Segment::Wildcard(name.to_string())
+                } else {
+                    Segment::Static(s.to_string())
+                }
+            })
+            .collect();
+        self.routes.push(Route {
+            method,
+            segments,
+            handler: Arc::new(handler),
+        });
+        self
+    }
+
+    pub fn get<H: Handler + 'static>(self, path: &str, handler: H) -> Self {
+        self.route(HttpMethod::Get, path, handler)
+    }
+    pub fn post<H: Handler + 'static>(self, path: &str, handler: H) -> Self {
+        self.route(HttpMethod::Post, path, handler)
+    }
+    pub fn put<H: Handler + 'static>(self, path: &str, handler: H) -> Self {
+        self.route(HttpMethod::Put, path, handler)
+    }
+    pub fn patch<H: Handler + 'static>(self, path: &str, handler: H) -> Self {
+        self.route(HttpMethod::Patch, path, handler)
+    }
+    pub fn delete<H: Handler + 'static>(self, path: &str, handler: H) -> Self {
+        self.route(HttpMethod::Delete, path, handler)
+    }
+
+    // 把子路由挂载到指定前缀下，子路由中的处理器看到的是剥离前缀后的路径
+    pub fn nest(mut self, prefix: &str, router: Router) -> Self {
+        let prefix = format!("/{}", prefix.trim_matches('/'));
+        self.mounts.push(Mount {
+            prefix: if prefix == "/" { String::new() } else { prefix },
+            router: Arc::new(router),
+        });
+        self
+    }
+
+    // 没有匹配到任何路由时使用的处理器
+    pub fn fallback<H: Handler + 'static>(mut self, handler: H) -> Self {
+        self.fallback = Some(Arc::new(handler));
+        self
+    }
+
+    // 添加中间件，只作用于当前路由及其子路由
+    pub fn middleware<M: Middleware + 'static>(mut self, middleware: M) -> Self {
+        self.middlewares.push(Arc::new(middleware));
+        self
+    }
+
+    // 共享状态，当前路由及其子路由的处理器可以通过State提取
+    pub fn state<T: Send + Sync + 'static>(mut self, state: T) -> Self {
+        self.states.push(Arc::new(state));
+        self
+    }
+
+    pub fn handle(&self, mut req: HttpRequest) -> HttpResponse<'static> {
+        for state in &self.states {
+            req.insert_extension(state.clone());
+        }
+        let endpoint = |req: HttpRequest| self.dispatch(req);
+        Next::new(&self.middlewares, &endpoint).run(req)
+    }
+
+    fn dispatch(&self, mut req: HttpRequest) -> HttpResponse<'static> {
+        let url = req.url().to_string();
+        // 当前路由
+        let mut allowed = Vec::new();
+        for route in &self.routes {
+            if let Some(params) = route.matches(&url) {
+                // HEAD请求可以使用GET的处理器
+                if route.method == *req.method()
+                    || (route.method == HttpMethod::Get && *req.method() == HttpMethod::Head)
+                {
+                    req.params_mut().extend(params);
+                    return route.handler.handle(&req);
+                }
+                allowed.push(route.method);
+            }
+        }
+        if !allowed.is_empty() {
+            // GET路由同样响应HEAD请求
+            if allowed.contains(&HttpMethod::Get) {
+                allowed.push(HttpMethod::Head);
+            }
+            let mut methods: Vec<&str> = allowed.iter().map(HttpMethod::as_str).collect();
+            methods.sort_unstable();
+            methods.dedup();
+            let mut response = HttpResponse::new(
+                HttpStatus::MethodNotAllowed,
+                None::<BTreeMap<&str, &str>>,
+                None,
+            );
+            response.set_header("Allow", methods.join(", "));
+            return response;
+        }
+        // 子路由
+        for mount in &self.mounts {
+            if let Some(rest) = mount.strip(&url) {
+                req.set_url(rest.to_string());
+                return mount.router.handle(req);
+            }
+        }
+        match &self.fallback {
+            Some(handler) => handler.handle(&req),
+            None => HttpResponse::not_found(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::Body;
+
+    fn request(method: &str, url: &str) -> String {
+        format!("{} {} HTTP/1.1\r\nHost: localhost\r\n", method, url)
+    }
+
+    // 响应体为处理器的名字和看到的路径
+    fn named(name: &'static str) -> impl Handler {
+        move |req: &HttpRequest| {
+            let body = format!("{} {} {:?}", name, req.url(), req.params());
+            HttpResponse::new(
+                HttpStatus::Ok,
+                None::<BTreeMap<&str, &str>>,
+                Some(body.into_bytes()),
+            )
+        }
+    }
+
+    fn send(router: &Router, method: &str, url: &str) -> HttpResponse<'static> {
+        let head = request(method, url);
+        router.handle(HttpRequest::from(&head, Vec::new(), "127.0.0.1").unwrap())
+    }
+
+    fn body(response: &HttpResponse) -> String {
+        match response.body() {
+            Some(Body::Full(bytes)) => String::from_utf8_lossy(bytes).to_string(),
+            _ => String::new(),
+        }
+    }
+
+    fn matches(path: &str, url: &str) -> Option<BTreeMap<String, String>> {
+        Router::new().get(path, named("route")).routes[0].matches(url)
+    }
+
+    #[test]
+    fn route_matches_static_and_params() {
+        assert_eq!(matches("/users", "/users"), Some(BTreeMap::new()));
+        assert_eq!(matches("/users/", "/users"), Some(BTreeMap::new()));
+        assert_eq!(matches("/users", "/users/1"), None);
+        assert_eq!(matches("/users/:id", "/users"), None);
+        assert_eq!(matches("/users/:id", "/posts/1"), None);
+        let params = matches("/users/:id/posts/:post", "/users/a%20b/posts/7").unwrap();
+        assert_eq!(params["id"], "a b");
+        assert_eq!(params["post"], "7");
+    }
+
+    #[test]
+    fn route_matches_wildcard() {
+        let params = matches("/files/*path", "/files/a/b%2Fc/d.txt").unwrap();
+        assert_eq!(params["path"], "a/b/c/d.txt");
+        assert_eq!(matches("/files/*path", "/files").unwrap()["path"], "");
+        assert_eq!(matches("/files/*path", "/other/a"), None);
+    }
+
+    #[test]
+    fn mount_strip_prefix() {
+        let mount = |prefix: &str| Router::new().nest(prefix, Router::new()).mounts.remove(0);
+        let api = mount("/api/");
+        assert_eq!(api.strip("/api"), Some("/"));
+        assert_eq!(api.strip("/api/"), Some("/"));
+        assert_eq!(api.strip("/api/users/1"), Some("/users/1"));
+        assert_eq!(api.strip("/apix"), None);
+        assert_eq!(api.strip("/other"), None);
+        // 挂载在根路径时不剥离
+        assert_eq!(mount("/").strip("/users"), Some("/users"));
+    }
+
+    #[test]
+    fn dispatch_routes_before_mounts_and_fallback() {
+        let router = Router::new()
+            .get("/api/status", named("status"))
+            .nest(
+                "/api",
+                Router::new()
+                    .get("/users/:id", named("user"))
+                    .fallback(named("api")),
+            )
+            .fallback(named("fallback"));
+        assert_eq!(
+            body(&send(&router, "GET", "/api/status")),
+            "status /api/status {}"
+        );
+        assert_eq!(
+            body(&send(&router, "GET", "/api/users/1")),
+            "user /users/1 {\"id\": \"1\"}"
+        );
+        assert_eq!(body(&send(&router, "GET", "/api/other")), "api /other {}");
+        assert_eq!(body(&send(&router, "GET", "/other")), "fallback /other {}");
+        // HEAD请求使用GET的处理器
+        assert_eq!(
+            body(&send(&router, "HEAD", "/api/status")),
+            "status /api/status {}"
+        );
+    }
+
+    #[test]
+    fn dispatch_method_not_allowed() {
+        let router = Router::new()
+            .get("/items", named("list"))
+            .post("/items", named("create"))
+            .delete("/items/:id", named("delete"))
+            .fallback(named("fallback"));
+        let response = send(&router, "PUT", "/items");
+        assert_eq!(*response.status(), HttpStatus::MethodNotAllowed);
+        assert_eq!(response.header("Allow"), Some("GET, HEAD, POST"));
+        let response = send(&router, "GET", "/items/1");
+        assert_eq!(response.header("Allow"), Some("DELETE"));
+        // 路径不匹配任何路由时交给fallback
+        assert_eq!(body(&send(&router, "PUT", "/other")), "fallback /other {}");
+    }
+
+    #[test]
+    fn dispatch_without_fallback() {
+        let router = Router::new().get("/", named("index"));
+        assert_eq!(
+            *send(&router, "GET", "/missing").status(),
+            HttpStatus::NotFound
+        );
     }
 }
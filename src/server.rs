@@ -1,12 +1,35 @@
+use crate::connection::{Connection, Rewind};
 use crate::error::{Fail, Result};
-use crate::request::HttpRequest;
-use crate::response::{HttpResponse, HttpStatus};
-use crate::router::Router;
+use crate::http2;
+use crate::listener::{self, ListenAddr, Listener, LocalAddr, PeerAddr};
+use crate::proxy::ProxyProtocol;
+use crate::request::{Extensions, HttpMethod, HttpRequest, HttpVersion};
+use crate::response::{Body, HttpResponse, HttpStatus, Upgrade};
+use crate::vhost::VirtualHosts;
 use std::collections::BTreeMap;
-use std::net::SocketAddr;
+use std::fs::File;
+use std::io::{self, SeekFrom};
+#[cfg(unix)]
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::{TcpListener, TcpStream};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+#[cfg(unix)]
+use tokio::signal::unix::{signal, SignalKind};
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+
+// TLS握手的超时时间
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// 读取请求头的超时时间，包括判断HTTP/2连接前言
+const HEADER_TIMEOUT: Duration = Duration::from_secs(10);
+
+// 热重启后等待旧连接处理完的最长时间
+#[cfg(unix)]
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
 
 #[derive(Clone, Debug)]
 pub struct HttpSettings {
@@ -19,6 +42,8 @@ pub struct HttpSettings {
     pub body_buffer: usize,
     pub header_read_attempts: usize,
     pub body_read_attempts: usize,
+    // 是否支持HTTP/2，TLS下通过ALPN协商，明文时需要客户端直接使用HTTP/2
+    pub http2: bool,
 }
 
 impl HttpSettings {
@@ -31,80 +56,456 @@ impl HttpSettings {
             body_buffer: 8192,
             header_read_attempts: 3,
             body_read_attempts: 3,
+            http2: true,
         }
     }
 }
 
+impl Default for HttpSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub struct Server {
-    socket_addr: SocketAddr,
+    // 监听地址，以及是否在该地址上使用TLS
+    listeners: Vec<(ListenAddr, bool)>,
+    // Unix套接字文件的权限
+    unix_mode: Option<u32>,
     http_settings: Arc<HttpSettings>,
+    hosts: Arc<VirtualHosts>,
+    tls: Option<Arc<ServerConfig>>,
+    proxy: Option<Arc<ProxyProtocol>>,
 }
 
 impl Server {
     // 构造方法
-    pub fn new(addr: &str, http_settings: HttpSettings) -> Self {
-        let socket_addr = addr.parse().unwrap();
-        let http_settings = Arc::new(http_settings);
-        Self {
-            socket_addr,
-            http_settings,
+    // 传入单个Router或按主机名分发的VirtualHosts
+    pub fn new<H>(addr: &str, http_settings: HttpSettings, hosts: H) -> Result<Self>
+    where
+        H: Into<VirtualHosts>,
+    {
+        let server = Self {
+            listeners: Vec::new(),
+            unix_mode: None,
+            http_settings: Arc::new(http_settings),
+            hosts: Arc::new(hosts.into()),
+            tls: None,
+            proxy: None,
+        };
+        server.listen(addr)
+    }
+
+    // 增加监听地址，支持 IP:端口、主机名:端口、unix:路径
+    // 以及已经打开的套接字 fd:编号 和 systemd 传递的全部套接字
+    // 配置了TLS时TCP地址上使用HTTPS，Unix套接字上不使用TLS
+    pub fn listen(mut self, addr: &str) -> Result<Self> {
+        for addr in ListenAddr::parse(addr)? {
+            self.listeners.push((addr, true));
+        }
+        Ok(self)
+    }
+
+    // 使用TLS，在处理请求前完成握手
+    pub fn tls(mut self, config: Arc<ServerConfig>) -> Self {
+        self.tls = Some(config);
+        self
+    }
+
+    // 同时在明文HTTP地址上监听，与TLS共用路由，通常配合HttpsRedirect使用
+    pub fn http_listener(mut self, addr: &str) -> Result<Self> {
+        for addr in ListenAddr::parse(addr)? {
+            self.listeners.push((addr, false));
         }
+        Ok(self)
+    }
+
+    // 在信任地址的连接上读取PROXY协议头，使用其中的客户端地址
+    pub fn proxy_protocol(mut self, proxy: ProxyProtocol) -> Self {
+        self.proxy = Some(Arc::new(proxy));
+        self
+    }
+
+    // Unix套接字文件的权限，如0o660
+    pub fn unix_mode(mut self, mode: u32) -> Self {
+        self.unix_mode = Some(mode);
+        self
+    }
+
+    // 按是否支持HTTP/2设置ALPN
+    fn tls_acceptor(&self) -> Option<TlsAcceptor> {
+        let mut config = ServerConfig::clone(self.tls.as_ref()?);
+        config.alpn_protocols = if self.http_settings.http2 {
+            vec![b"h2".to_vec(), b"http/1.1".to_vec()]
+        } else {
+            vec![b"http/1.1".to_vec()]
+        };
+        Some(TlsAcceptor::from(Arc::new(config)))
     }
 
     // 运行
+    // Unix上收到SIGUSR2时热重启，新进程接管监听套接字后停止接受连接，等待已有连接处理完再返回
     pub async fn run(&self) -> Result<()> {
-        // 监听
-        let conn_listener = TcpListener::bind(self.socket_addr).await?;
-        println!("Running on {}", self.socket_addr);
-        loop {
-            // 处理每个连接
-            if let Ok((stream, address)) = conn_listener.accept().await {
-                let http_settings = self.http_settings.clone();
-                // 开启一个异步任务
-                tokio::spawn(async move {
-                    let mut stream = stream;
-                    match handle_conn(&http_settings, &mut stream, address).await {
-                        Ok(_) => {}
-                        Err(err) => {
-                            println!("{}", err);
-                            write_stream(
-                                &mut stream,
-                                HttpResponse::new(
-                                    HttpStatus::BadRequest,
-                                    None::<BTreeMap<&str, &str>>,
-                                    Some(err.to_string().as_bytes().to_vec()),
-                                )
-                                .to_vec(),
-                            )
-                            .await;
-                        }
-                    };
-                });
+        // 全部地址绑定成功后再开始接受连接
+        let addrs: Vec<ListenAddr> = self.listeners.iter().map(|(a, _)| a.clone()).collect();
+        let mut listeners = Vec::new();
+        for (addr, secure) in &self.listeners {
+            let listener = Listener::bind(addr, &addrs, self.unix_mode)?;
+            // 监听的类型和端口在这里确定，处理请求时不再查询
+            let local = match listener.local_addr() {
+                Ok(local) => local,
+                Err(err) => return Fail::from(format!("监听 {} 失败: {}", addr, err)),
+            };
+            listeners.push((local, listener, *secure));
+        }
+        // 旧进程传递的、新配置中已经没有的地址不再监听
+        listener::close_inherited();
+        #[cfg(unix)]
+        let fds: Vec<RawFd> = listeners.iter().map(|(_, l, _)| l.as_raw_fd()).collect();
+        let tls = self.tls_acceptor();
+        let active = Arc::new(AtomicUsize::new(0));
+        let mut tasks = Vec::new();
+        for (local, listener, secure) in listeners {
+            // 继承的fd可能是Unix套接字，按实际类型决定是否使用TLS
+            let tls = match (&tls, &listener) {
+                (Some(tls), Listener::Tcp(_)) if secure => Some(tls.clone()),
+                _ => None,
+            };
+            println!("Running on {}", listener.url(tls.is_some()));
+            tasks.push(tokio::spawn(accept(
+                listener,
+                Arc::new(local),
+                tls,
+                self.http_settings.clone(),
+                self.hosts.clone(),
+                self.proxy.clone(),
+                active.clone(),
+            )));
+        }
+        #[cfg(unix)]
+        if let Ok(mut restart) = signal(SignalKind::user_defined2()) {
+            loop {
+                // 信号流关闭后不会再收到信号，不再热重启，只继续接受连接
+                if restart.recv().await.is_none() {
+                    std::future::pending::<()>().await;
+                }
+                match listener::spawn_successor(&fds).await {
+                    Ok(_) => break,
+                    // 新进程启动失败时继续使用旧进程
+                    Err(err) => println!("热重启失败: {}", err),
+                }
             }
+            for task in &tasks {
+                task.abort();
+            }
+            drain(&active).await;
+            return Ok(());
+        }
+        for task in tasks {
+            let _ = task.await;
         }
+        Ok(())
+    }
+}
+
+// 等待已有连接处理完，长连接最多等待DRAIN_TIMEOUT
+#[cfg(unix)]
+async fn drain(active: &AtomicUsize) {
+    let deadline = tokio::time::Instant::now() + DRAIN_TIMEOUT;
+    while active.load(Ordering::Relaxed) > 0 && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+// 正在处理的连接计数，连接任务结束时减少
+struct Active(Arc<AtomicUsize>);
+
+impl Active {
+    fn new(count: Arc<AtomicUsize>) -> Self {
+        count.fetch_add(1, Ordering::Relaxed);
+        Self(count)
+    }
+}
+
+impl Drop for Active {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// 接受连接，每个连接在单独的任务中处理
+async fn accept(
+    listener: Listener,
+    listen_addr: Arc<LocalAddr>,
+    tls: Option<TlsAcceptor>,
+    http_settings: Arc<HttpSettings>,
+    hosts: Arc<VirtualHosts>,
+    proxy: Option<Arc<ProxyProtocol>>,
+    active: Arc<AtomicUsize>,
+) {
+    loop {
+        // 处理每个连接
+        let (mut stream, mut address) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(_) => continue,
+        };
+        let http_settings = http_settings.clone();
+        let hosts = hosts.clone();
+        let tls = tls.clone();
+        let listen_addr = listen_addr.clone();
+        let proxy = proxy.clone();
+        let active = Active::new(active.clone());
+        // 开启一个异步任务
+        tokio::spawn(async move {
+            let _active = active;
+            // PROXY协议头在TLS握手之前
+            let mut proxied = None;
+            if let Some(proxy) = proxy.filter(|proxy| proxy.trusted(&address)) {
+                match proxy.accept(&mut stream).await {
+                    Ok(info) => proxied = info,
+                    Err(err) => {
+                        println!("{}: {}", address, err);
+                        return;
+                    }
+                }
+            }
+            if let Some(info) = &proxied {
+                address = PeerAddr::Tcp(info.source);
+            }
+            let mut stream = match (tls, stream) {
+                (Some(acceptor), Connection::Plain(stream)) => {
+                    let handshake = acceptor.accept(stream);
+                    match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, handshake).await {
+                        Ok(Ok(stream)) => Connection::Tls(Box::new(stream)),
+                        Ok(Err(err)) => {
+                            println!("TLS握手失败: {}", err);
+                            return;
+                        }
+                        Err(_) => return,
+                    }
+                }
+                (_, stream) => stream,
+            };
+            let mut extensions: Extensions = vec![listen_addr, Arc::new(address)];
+            if let Some(info) = proxied {
+                extensions.push(Arc::new(info));
+            }
+            if let Some(tls) = stream.tls_info() {
+                extensions.push(Arc::new(tls));
+            }
+            // 检查连接前言时读出的数据
+            let (h2, preface) = match &mut stream {
+                Connection::Tls(stream) => (
+                    stream.get_ref().1.alpn_protocol() == Some(b"h2"),
+                    Vec::new(),
+                ),
+                stream if http_settings.http2 => {
+                    // 超时还没有发送完连接前言的连接直接关闭
+                    match tokio::time::timeout(HEADER_TIMEOUT, http2::read_preface(stream)).await {
+                        Ok(Ok(read)) => read,
+                        _ => return,
+                    }
+                }
+                _ => (false, Vec::new()),
+            };
+            if h2 {
+                let stream = Rewind::new(preface, stream);
+                if let Err(err) =
+                    http2::serve(stream, http_settings, hosts, address, extensions).await
+                {
+                    println!("{}", err);
+                }
+                return;
+            }
+            match handle_conn(
+                &http_settings,
+                &hosts,
+                &mut stream,
+                preface,
+                address,
+                extensions,
+            )
+            .await
+            {
+                // 协议升级后连接交给升级的处理方
+                Ok(Some((upgrade, buffer))) => {
+                    upgrade.run(stream, buffer).await;
+                    return;
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    println!("{}", err);
+                    write_stream(
+                        &mut stream,
+                        HttpResponse::new(
+                            HttpStatus::BadRequest,
+                            None::<BTreeMap<&str, &str>>,
+                            Some(err.to_string().as_bytes().to_vec()),
+                        )
+                        .to_vec(),
+                    )
+                    .await;
+                }
+            };
+            let _ = stream.shutdown().await;
+        });
     }
 }
 
 async fn handle_conn(
     http_settings: &HttpSettings,
-    mut stream: &mut TcpStream,
-    addr: SocketAddr,
-) -> Result<()> {
+    hosts: &VirtualHosts,
+    stream: &mut Connection,
+    preface: Vec<u8>,
+    addr: PeerAddr,
+    extensions: Extensions,
+) -> Result<Option<(Upgrade, Vec<u8>)>> {
     // 读取请求
-    let (header, mut body) = read_head(http_settings, &mut stream).await?;
+    let (header, mut body) =
+        match tokio::time::timeout(HEADER_TIMEOUT, read_head(http_settings, stream, preface)).await
+        {
+            Ok(result) => result?,
+            Err(_) => return Fail::from("读取请求头超时"),
+        };
     let content_length = get_content_length(header.as_str());
-    if content_length > 0 {
-        read_body(&http_settings, &mut stream, &mut body, content_length).await?;
+    // 没有请求体时，请求头之后读到的数据属于升级后的协议
+    let buffer = if content_length > 0 {
+        read_body(http_settings, stream, &mut body, content_length).await?;
+        Vec::new()
+    } else {
+        body.clone()
+    };
+    let ip = addr.request_ip();
+    let mut request = HttpRequest::from(&header, body, &ip[..])?;
+    for extension in extensions {
+        request.insert_extension(extension);
+    }
+    let head_only = *request.method() == HttpMethod::Head;
+    // HTTP/2只能通过TLS协商或连接前言使用，不能用HTTP/1的格式发送
+    let mut response = if *request.version() == HttpVersion::V2_0 {
+        HttpResponse::new(
+            HttpStatus::HttpVersionNotSupported,
+            None::<BTreeMap<&str, &str>>,
+            None,
+        )
+    } else {
+        hosts.handle(request)
+    };
+    let upgrade = match response.status() {
+        HttpStatus::SwitchingProtocols => response.take_upgrade(),
+        _ => None,
+    };
+    write_response(stream, response, head_only).await;
+    Ok(upgrade.map(|upgrade| (upgrade, buffer)))
+}
+
+// 发送响应，流式响应体使用chunked编码，写入失败时丢弃receiver使发送端停止
+// HEAD请求只发送状态行和响应头，Content-Length等与GET相同
+async fn write_response(stream: &mut Connection, mut response: HttpResponse<'_>, head_only: bool) {
+    let head = response.head();
+    if head_only {
+        write_stream(stream, head).await;
+        return;
+    }
+    match response.take_body() {
+        Some(Body::Stream(mut receiver)) => {
+            if stream.write_all(&head).await.is_err() {
+                return;
+            }
+            while let Some(chunk) = receiver.recv().await {
+                let chunk = match chunk {
+                    Ok(chunk) if chunk.is_empty() => continue,
+                    Ok(chunk) => chunk,
+                    // 不发送结束的空chunk，客户端按chunked编码能发现响应不完整
+                    Err(err) => {
+                        println!("{}", err);
+                        return;
+                    }
+                };
+                let mut data = format!("{:x}\r\n", chunk.len()).into_bytes();
+                data.extend_from_slice(&chunk);
+                data.extend_from_slice(b"\r\n");
+                if stream.write_all(&data).await.is_err() || stream.flush().await.is_err() {
+                    return;
+                }
+            }
+            write_stream(stream, b"0\r\n\r\n".to_vec()).await;
+        }
+        Some(Body::File { file, offset, len }) => {
+            if stream.write_all(&head).await.is_err() {
+                return;
+            }
+            let sent = match stream {
+                Connection::Plain(stream) => send_file(stream, file, offset, len).await,
+                // TLS需要加密，只能读出后发送
+                stream => copy_file(stream, file, offset, len).await,
+            };
+            if let Err(err) = sent {
+                println!("{}", err);
+            }
+        }
+        Some(Body::Full(body)) => write_stream(stream, [&head[..], &body[..]].concat()).await,
+        None => write_stream(stream, head).await,
+    }
+}
+
+// Linux上使用sendfile直接从文件发送到socket
+#[cfg(target_os = "linux")]
+async fn send_file(stream: &mut TcpStream, file: File, offset: u64, len: u64) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+    use tokio::io::Interest;
+
+    let (socket_fd, file_fd) = (stream.as_raw_fd(), file.as_raw_fd());
+    let mut position = offset as libc::off_t;
+    let end = (offset + len) as libc::off_t;
+    while position < end {
+        let count = (end - position).min(1 << 30) as usize;
+        let sent = stream
+            .async_io(Interest::WRITABLE, || {
+                // SAFETY: 两个fd在调用期间都有效，position指向有效的off_t
+                let sent = unsafe { libc::sendfile(socket_fd, file_fd, &mut position, count) };
+                if sent < 0 {
+                    Err(io::Error::last_os_error())
+                } else {
+                    Ok(sent as usize)
+                }
+            })
+            .await;
+        match sent {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(_) => {}
+            // 文件系统不支持sendfile时改为普通读写
+            Err(err) if position as u64 == offset && err.raw_os_error() == Some(libc::EINVAL) => {
+                return copy_file(stream, file, offset, len).await;
+            }
+            Err(err) => return Err(err),
+        }
     }
-    let ip = addr.ip().to_string();
-    let request = HttpRequest::from(&header, body, &ip[..])?;
-    let response = Router::route(request);
-    write_stream(stream, response.to_vec()).await;
     Ok(())
 }
 
+#[cfg(not(target_os = "linux"))]
+async fn send_file(stream: &mut TcpStream, file: File, offset: u64, len: u64) -> io::Result<()> {
+    copy_file(stream, file, offset, len).await
+}
+
+// 分块读取文件并发送
+async fn copy_file<W>(stream: &mut W, file: File, offset: u64, len: u64) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut file = tokio::fs::File::from_std(file);
+    file.seek(SeekFrom::Start(offset)).await?;
+    let mut reader = BufReader::with_capacity(64 * 1024, file.take(len));
+    let copied = tokio::io::copy_buf(&mut reader, stream).await?;
+    if copied < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    stream.flush().await
+}
+
 // 响应数据
-async fn write_stream(stream: &mut TcpStream, content: Vec<u8>) {
+async fn write_stream(stream: &mut Connection, content: Vec<u8>) {
     match stream.write_all(&content).await {
         Ok(_) => match stream.flush().await {
             Ok(_) => {}
@@ -119,9 +520,11 @@ async fn write_stream(stream: &mut TcpStream, content: Vec<u8>) {
 }
 
 // 读取请求头
+// pending为检查连接前言时已经读出的数据
 async fn read_head(
     http_settings: &HttpSettings,
-    stream: &mut TcpStream,
+    stream: &mut Connection,
+    mut pending: Vec<u8>,
 ) -> Result<(String, Vec<u8>)> {
     // 初始化缓存
     let mut header = Vec::new();
@@ -131,8 +534,17 @@ async fn read_head(
     // 不停地读取流，直到读完请求头结束
     let mut read_fails = 0;
     'l: loop {
+        // 先使用已经读出的数据，不计入读取次数
+        let replayed = !pending.is_empty();
+        let length = if replayed {
+            let length = pending.len().min(buf.len());
+            buf[..length].copy_from_slice(&pending[..length]);
+            pending.drain(..length);
+            length
+        } else {
+            stream.read(&mut buf).await?
+        };
         // 检查请求头是否超过限制
-        let length = stream.read(&mut buf).await?;
         if header.len() + length > http_settings.max_header_size {
             return Fail::from("请求头大小超出限制");
         }
@@ -148,8 +560,8 @@ async fn read_head(
                     let mut buf_temp = vec![0u8; i + 4 - buf.len()];
                     stream.read_exact(&mut buf_temp).await?;
                     // 合并缓冲区
-                    let mut buf2 = [buf, &buf_temp].concat();
-                    header.append(&mut buf2);
+                    let buf2 = [buf, &buf_temp].concat();
+                    header.extend_from_slice(&buf2);
                     // 检查请求头是否读取完毕 \n\r\n
                     if buf2[i + 1] == b'\n' && buf2[i + 2] == b'\r' && buf2[i + 3] == b'\n' {
                         break 'l;
@@ -172,7 +584,7 @@ async fn read_head(
             }
         }
         // 如果没有读取完毕，重试
-        if length < http_settings.header_buffer {
+        if length < http_settings.header_buffer && !replayed {
             read_fails += 1;
             // 超出重试次数返回错误信息
             if read_fails > http_settings.header_read_attempts {
@@ -191,7 +603,7 @@ fn get_content_length(head: &str) -> usize {
         let mut split_hl = hl.splitn(2, ":");
         if let (Some(key), Some(value)) = (split_hl.next(), split_hl.next()) {
             if key.trim().to_lowercase().eq("content-length") {
-                size = match value.parse::<usize>() {
+                size = match value.trim().parse::<usize>() {
                     Ok(s) => s,
                     Err(_) => 0,
                 };
@@ -204,7 +616,7 @@ fn get_content_length(head: &str) -> usize {
 // 读取完整的body
 async fn read_body(
     http_settings: &HttpSettings,
-    stream: &mut TcpStream,
+    stream: &mut Connection,
     body: &mut Vec<u8>,
     content_len: usize,
 ) -> Result<()> {
@@ -240,3 +652,63 @@ async fn read_body(
     }
     Ok(())
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::UnixStream;
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn read_head_after_preface_check() {
+        let (server, mut client) = UnixStream::pair().unwrap();
+        let mut connection = Connection::Unix(server);
+        // 检查连接前言时读出的24字节正好停在\r上
+        let pending = b"GET / HTTP/1.1\r\nHost: x\r".to_vec();
+        client.write_all(b"\n\r\nbody").await.unwrap();
+        let (header, body) = read_head(&HttpSettings::new(), &mut connection, pending)
+            .await
+            .unwrap();
+        assert_eq!(header, "GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+        assert!(body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn stream_error_skips_terminator() {
+        let (server, mut client) = UnixStream::pair().unwrap();
+        let mut connection = Connection::Unix(server);
+        let (sender, receiver) = mpsc::channel(4);
+        sender.send(Ok(b"abc".to_vec())).await.unwrap();
+        sender
+            .send(Err(io::ErrorKind::UnexpectedEof.into()))
+            .await
+            .unwrap();
+        sender.send(Ok(b"def".to_vec())).await.unwrap();
+        let response = HttpResponse::stream(HttpStatus::Ok, None::<BTreeMap<&str, &str>>, receiver);
+        write_response(&mut connection, response, false).await;
+        drop(connection);
+
+        let mut data = Vec::new();
+        client.read_to_end(&mut data).await.unwrap();
+        let data = String::from_utf8(data).unwrap();
+        assert!(data.contains("Transfer-Encoding: chunked"));
+        assert!(data.ends_with("\r\n\r\n3\r\nabc\r\n"));
+    }
+
+    #[tokio::test]
+    async fn stream_complete_with_terminator() {
+        let (server, mut client) = UnixStream::pair().unwrap();
+        let mut connection = Connection::Unix(server);
+        let (sender, receiver) = mpsc::channel(4);
+        sender.send(Ok(b"abc".to_vec())).await.unwrap();
+        drop(sender);
+        let response = HttpResponse::stream(HttpStatus::Ok, None::<BTreeMap<&str, &str>>, receiver);
+        write_response(&mut connection, response, false).await;
+        drop(connection);
+
+        let mut data = Vec::new();
+        client.read_to_end(&mut data).await.unwrap();
+        assert!(data.ends_with(b"3\r\nabc\r\n0\r\n\r\n"));
+    }
+}
@@ -0,0 +1,187 @@
+use crate::error::{Fail, Result};
+use crate::extract::{FromRequest, Rejection};
+use crate::request::HttpRequest;
+use crate::response::{HttpResponse, HttpStatus};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::{interval_at, Instant};
+
+// 默认的保活注释间隔
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+// 一条事件，字段中的换行按协议拆分或去掉
+#[derive(Clone, Debug, Default)]
+pub struct Event {
+    event: Option<String>,
+    data: Option<String>,
+    id: Option<String>,
+    retry: Option<Duration>,
+    comment: Option<String>,
+}
+
+// event和id只能有一行
+fn single_line(value: &str) -> String {
+    value.replace(['\r', '\n'], "")
+}
+
+impl Event {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 多行数据拆分为多个data字段，客户端收到后用换行拼接
+    pub fn data(mut self, data: &str) -> Self {
+        self.data = Some(data.to_string());
+        self
+    }
+
+    pub fn event(mut self, event: &str) -> Self {
+        self.event = Some(single_line(event));
+        self
+    }
+
+    // 客户端重连时通过Last-Event-ID发送最后收到的id
+    pub fn id(mut self, id: &str) -> Self {
+        self.id = Some(single_line(id).replace('\0', ""));
+        self
+    }
+
+    // 客户端断开后重连的等待时间
+    pub fn retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    // 注释，客户端会忽略
+    pub fn comment(mut self, comment: &str) -> Self {
+        self.comment = Some(comment.to_string());
+        self
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut text = String::new();
+        if let Some(comment) = &self.comment {
+            for line in comment.lines() {
+                text.push_str(&format!(": {}\n", line));
+            }
+        }
+        if let Some(event) = &self.event {
+            text.push_str(&format!("event: {}\n", event));
+        }
+        if let Some(id) = &self.id {
+            text.push_str(&format!("id: {}\n", id));
+        }
+        if let Some(retry) = &self.retry {
+            text.push_str(&format!("retry: {}\n", retry.as_millis()));
+        }
+        if let Some(data) = &self.data {
+            // 空数据也要发送一个data字段，否则客户端不会触发事件
+            let data = data.replace("\r\n", "\n").replace('\r', "\n");
+            for line in data.split('\n') {
+                text.push_str(&format!("data: {}\n", line));
+            }
+        }
+        text.push('\n');
+        text.into_bytes()
+    }
+}
+
+// 发送事件，客户端断开后发送失败
+#[derive(Clone, Debug)]
+pub struct EventSender(mpsc::Sender<Event>);
+
+impl EventSender {
+    pub async fn send(&self, event: Event) -> Result<()> {
+        match self.0.send(event).await {
+            Ok(_) => Ok(()),
+            Err(_) => Fail::from("客户端已断开"),
+        }
+    }
+
+    // 客户端断开时完成，用于在等待其他数据时及时停止
+    pub async fn closed(&self) {
+        self.0.closed().await
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.0.is_closed()
+    }
+}
+
+// 从请求中提取的SSE，在处理器中调用stream返回事件流响应
+#[derive(Debug)]
+pub struct Sse {
+    // 客户端重连时发送的最后一个事件id
+    last_event_id: Option<String>,
+    // 保活注释的间隔，为0时不发送
+    keep_alive: Duration,
+}
+
+impl FromRequest for Sse {
+    fn from_request(req: &HttpRequest) -> std::result::Result<Self, Rejection> {
+        Ok(Self {
+            last_event_id: req
+                .headers()
+                .get("last-event-id")
+                .map(|id| id.trim().to_string()),
+            keep_alive: KEEP_ALIVE,
+        })
+    }
+}
+
+impl Sse {
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.last_event_id.as_deref()
+    }
+
+    // 代理和客户端可能关闭长时间没有数据的连接，定期发送注释保持连接
+    // 断开的连接也要在写入时才能发现，间隔越短生产事件的任务越早停止
+    pub fn keep_alive(mut self, interval: Duration) -> Self {
+        self.keep_alive = interval;
+        self
+    }
+
+    // 返回事件流响应，producer在单独的任务中发送事件，客户端断开后发送失败
+    pub fn stream<F, Fut>(self, producer: F) -> HttpResponse<'static>
+    where
+        F: FnOnce(EventSender) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let (sender, mut events) = mpsc::channel::<Event>(16);
+        let (body, receiver) = mpsc::channel(16);
+        let keep_alive = self.keep_alive;
+        tokio::spawn(async move {
+            // 不发送保活注释时计时器不会被轮询，周期只需要合法
+            let period = if keep_alive.is_zero() {
+                KEEP_ALIVE
+            } else {
+                keep_alive
+            };
+            let mut ticker = interval_at(Instant::now() + period, period);
+            loop {
+                let data = tokio::select! {
+                    event = events.recv() => match event {
+                        Some(event) => event.to_bytes(),
+                        None => return,
+                    },
+                    _ = ticker.tick(), if !keep_alive.is_zero() => b":\n\n".to_vec(),
+                    // 连接断开后丢弃events，producer的发送失败
+                    _ = body.closed() => return,
+                };
+                if body.send(Ok(data)).await.is_err() {
+                    return;
+                }
+            }
+        });
+        tokio::spawn(producer(EventSender(sender)));
+
+        let mut headers = BTreeMap::new();
+        headers.insert("Content-Type", "text/event-stream");
+        headers.insert("Cache-Control", "no-cache");
+        // 让nginx等反向代理不缓冲事件流
+        headers.insert("X-Accel-Buffering", "no");
+        HttpResponse::stream(HttpStatus::Ok, Some(headers), receiver)
+    }
+}
@@ -0,0 +1,470 @@
+// 本地试用HTTPS时可以先生成自签名证书：
+//   openssl req -x509 -newkey rsa:2048 -nodes -days 365 -subj /CN=localhost \
+//     -addext subjectAltName=DNS:localhost -keyout key.pem -out cert.pem
+//   cargo run -- --addr 127.0.0.1:8443 --tls-cert cert.pem --tls-key key.pem
+//   curl -k https://localhost:8443/
+// 多个域名使用不同证书时用 --tls-sni "*.example.com cert.pem key.pem"
+use crate::error::{Fail, Result};
+use crate::vhost::{find_host, HostPattern};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier};
+use rustls::sign::CertifiedKey;
+use rustls::{ProtocolVersion, RootCertStore, ServerConfig, ServerConnection};
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+use x509_parser::certificate::X509Certificate;
+use x509_parser::extensions::GeneralName;
+use x509_parser::prelude::FromDer;
+
+// 检查证书文件是否变化的间隔
+const CERT_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+// TLS连接的信息，作为请求的扩展传给handler
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub struct TlsInfo {
+    // 协议版本，如 TLSv1.3
+    pub version: String,
+    // ALPN协商的应用层协议，如 http/1.1
+    pub alpn: Option<String>,
+    // 客户端通过SNI请求的主机名
+    pub server_name: Option<String>,
+    // 客户端证书链，DER格式，第一个是客户端自己的证书
+    pub peer_certificates: Vec<Vec<u8>>,
+    // 通过验证的客户端证书信息
+    pub client: Option<ClientCert>,
+}
+
+// 客户端证书的主体和备用名称
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub struct ClientCert {
+    // 如 CN=alice, O=Example
+    pub subject: String,
+    // 如 DNS:alice.example.com、email:alice@example.com、IP:10.0.0.1
+    pub san: Vec<String>,
+}
+
+impl ClientCert {
+    fn parse(der: &[u8]) -> Option<Self> {
+        let (_, cert) = X509Certificate::from_der(der).ok()?;
+        let san = match cert.subject_alternative_name() {
+            Ok(Some(san)) => san
+                .value
+                .general_names
+                .iter()
+                .filter_map(|name| match name {
+                    GeneralName::DNSName(dns) => Some(format!("DNS:{}", dns)),
+                    GeneralName::RFC822Name(email) => Some(format!("email:{}", email)),
+                    GeneralName::URI(uri) => Some(format!("URI:{}", uri)),
+                    GeneralName::IPAddress(ip) => ip_address(ip).map(|ip| format!("IP:{}", ip)),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        Some(Self {
+            subject: cert.subject().to_string(),
+            san,
+        })
+    }
+
+    // 主体中的CN
+    #[allow(dead_code)]
+    pub fn common_name(&self) -> Option<&str> {
+        self.subject
+            .split(',')
+            .find_map(|part| part.trim().strip_prefix("CN="))
+    }
+}
+
+fn ip_address(bytes: &[u8]) -> Option<IpAddr> {
+    match bytes.len() {
+        4 => <[u8; 4]>::try_from(bytes).ok().map(IpAddr::from),
+        16 => <[u8; 16]>::try_from(bytes).ok().map(IpAddr::from),
+        _ => None,
+    }
+}
+
+// 客户端证书验证方式
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClientAuth {
+    // 必须提供有效的证书
+    Required,
+    // 可以不提供证书，提供时必须有效
+    Optional,
+}
+
+impl TlsInfo {
+    pub fn from_connection(conn: &ServerConnection) -> Self {
+        let version = match conn.protocol_version() {
+            Some(ProtocolVersion::TLSv1_2) => String::from("TLSv1.2"),
+            Some(ProtocolVersion::TLSv1_3) => String::from("TLSv1.3"),
+            Some(version) => format!("{:?}", version),
+            None => String::new(),
+        };
+        let peer_certificates: Vec<Vec<u8>> = conn
+            .peer_certificates()
+            .map(|certs| certs.iter().map(|c| c.to_vec()).collect())
+            .unwrap_or_default();
+        Self {
+            version,
+            alpn: conn
+                .alpn_protocol()
+                .map(|p| String::from_utf8_lossy(p).to_string()),
+            server_name: conn.server_name().map(str::to_string),
+            client: peer_certificates
+                .first()
+                .and_then(|cert| ClientCert::parse(cert)),
+            peer_certificates,
+        }
+    }
+}
+
+// 读取PEM格式的证书链
+pub fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let file = File::open(path)
+        .map_err(|e| Fail::new(format!("无法读取证书 {}: {}", path.display(), e)))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file)).collect::<io::Result<Vec<_>>>()?;
+    if certs.is_empty() {
+        return Fail::from(format!("{} 中没有证书", path.display()));
+    }
+    Ok(certs)
+}
+
+// 读取PEM格式的私钥，支持PKCS#1、PKCS#8和SEC1
+pub fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>> {
+    let file = File::open(path)
+        .map_err(|e| Fail::new(format!("无法读取私钥 {}: {}", path.display(), e)))?;
+    match rustls_pemfile::private_key(&mut BufReader::new(file))? {
+        Some(key) => Ok(key),
+        None => Fail::from(format!("{} 中没有私钥", path.display())),
+    }
+}
+
+// 读取证书链和对应的私钥
+fn certified_key(cert: &Path, key: &Path) -> Result<Arc<CertifiedKey>> {
+    let provider = rustls::crypto::ring::default_provider();
+    let certified = CertifiedKey::from_der(load_certs(cert)?, load_key(key)?, &provider)
+        .map_err(|e| Fail::new(format!("证书 {} 和私钥无效: {}", cert.display(), e)))?;
+    Ok(Arc::new(certified))
+}
+
+// 一组证书和私钥文件，host为None时作为默认证书
+#[derive(Clone, Debug)]
+pub struct CertSource {
+    pub host: Option<String>,
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+#[derive(Debug, Default)]
+struct Certs {
+    hosts: Vec<(HostPattern, Arc<CertifiedKey>)>,
+    default: Option<Arc<CertifiedKey>>,
+}
+
+// 按SNI选择证书，文件变化或收到SIGHUP时重新加载，已建立的连接不受影响
+#[derive(Debug)]
+pub struct CertStore {
+    sources: Vec<CertSource>,
+    certs: RwLock<Arc<Certs>>,
+    // 上次加载时各文件的修改时间
+    modified: Mutex<Vec<Option<SystemTime>>>,
+}
+
+impl CertStore {
+    pub fn new(sources: Vec<CertSource>) -> Result<Self> {
+        let store = Self {
+            certs: RwLock::new(Arc::new(Certs::default())),
+            modified: Mutex::new(Vec::new()),
+            sources,
+        };
+        store.reload()?;
+        Ok(store)
+    }
+
+    // 重新读取所有证书，失败时保留原来的证书
+    pub fn reload(&self) -> Result<()> {
+        // 加载前记录修改时间，加载失败时保留旧的时间，下次检查时重试
+        let times = self.modified_times();
+        let mut certs = Certs::default();
+        for source in &self.sources {
+            let key = certified_key(&source.cert, &source.key)?;
+            match &source.host {
+                Some(host) => certs.hosts.push((HostPattern::parse(host), key)),
+                None => certs.default = Some(key),
+            }
+        }
+        if let Ok(mut current) = self.certs.write() {
+            *current = Arc::new(certs);
+        }
+        if let Ok(mut modified) = self.modified.lock() {
+            *modified = times;
+        }
+        Ok(())
+    }
+
+    fn modified_times(&self) -> Vec<Option<SystemTime>> {
+        self.sources
+            .iter()
+            .flat_map(|source| [&source.cert, &source.key])
+            .map(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+            .collect()
+    }
+
+    fn changed(&self) -> bool {
+        match self.modified.lock() {
+            Ok(modified) => *modified != self.modified_times(),
+            Err(_) => false,
+        }
+    }
+
+    // 后台检查文件修改时间并监听SIGHUP
+    pub fn watch(self: &Arc<Self>) {
+        let store = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(CERT_CHECK_INTERVAL);
+            let mut hangup = Hangup::new();
+            loop {
+                let forced = tokio::select! {
+                    _ = interval.tick() => false,
+                    _ = hangup.recv() => true,
+                };
+                if !forced && !store.changed() {
+                    continue;
+                }
+                match store.reload() {
+                    Ok(_) => println!("已重新加载证书"),
+                    Err(err) => println!("重新加载证书失败: {}", err),
+                }
+            }
+        });
+    }
+}
+
+impl CertStore {
+    // 按SNI主机名选择证书，没有匹配时使用默认证书
+    fn select(&self, server_name: Option<&str>) -> Option<Arc<CertifiedKey>> {
+        let certs = self.certs.read().ok()?.clone();
+        server_name
+            .and_then(|name| find_host(&certs.hosts, &name.to_lowercase()))
+            .or(certs.default.as_ref())
+            .cloned()
+    }
+}
+
+impl ResolvesServerCert for CertStore {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        self.select(client_hello.server_name())
+    }
+}
+
+// SIGHUP信号，不支持时永远不会收到
+struct Hangup {
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl Hangup {
+    fn new() -> Self {
+        Self {
+            #[cfg(unix)]
+            signal: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()).ok(),
+        }
+    }
+
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = &mut self.signal {
+            if signal.recv().await.is_some() {
+                return;
+            }
+            // 信号流已经关闭，之后不会再收到信号
+            self.signal = None;
+        }
+        std::future::pending::<()>().await
+    }
+}
+
+// 使用证书库生成服务端配置，client_auth为客户端证书的CA和验证方式
+pub fn server_config(
+    store: Arc<CertStore>,
+    client_auth: Option<(&Path, ClientAuth)>,
+) -> Result<Arc<ServerConfig>> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+    let builder = match client_auth {
+        Some((ca, mode)) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(ca)? {
+                roots
+                    .add(cert)
+                    .map_err(|e| Fail::new(format!("无效的CA证书 {}: {}", ca.display(), e)))?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+            let verifier = match mode {
+                ClientAuth::Required => verifier,
+                ClientAuth::Optional => verifier.allow_unauthenticated(),
+            };
+            builder.with_client_cert_verifier(verifier.build()?)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    Ok(Arc::new(builder.with_cert_resolver(store)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{CertificateParams, DnType, KeyPair, SanType};
+
+    // 每个测试使用单独的临时目录
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tls-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // 生成自签名证书，写入 name.pem 和 name.key，返回证书的DER
+    fn generate(dir: &Path, name: &str, hosts: &[&str]) -> (CertSource, Vec<u8>) {
+        let hosts: Vec<String> = hosts.iter().map(|h| h.to_string()).collect();
+        let key = KeyPair::generate().unwrap();
+        let cert = CertificateParams::new(hosts)
+            .unwrap()
+            .self_signed(&key)
+            .unwrap();
+        let source = CertSource {
+            host: None,
+            cert: dir.join(format!("{}.pem", name)),
+            key: dir.join(format!("{}.key", name)),
+        };
+        fs::write(&source.cert, cert.pem()).unwrap();
+        fs::write(&source.key, key.serialize_pem()).unwrap();
+        (source, cert.der().to_vec())
+    }
+
+    fn error<T>(result: Result<T>) -> String {
+        match result {
+            Ok(_) => String::new(),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    // 选中证书的DER
+    fn selected(store: &CertStore, server_name: Option<&str>) -> Option<Vec<u8>> {
+        store.select(server_name).map(|key| key.cert[0].to_vec())
+    }
+
+    #[test]
+    fn load_errors() {
+        let dir = temp_dir("load");
+        let (source, _) = generate(&dir, "a", &["localhost"]);
+        let missing = dir.join("missing.pem");
+        let empty = dir.join("empty.pem");
+        fs::write(&empty, "not a pem file\n").unwrap();
+
+        assert!(error(load_certs(&missing)).contains("无法读取证书"));
+        assert!(error(load_certs(&empty)).contains("中没有证书"));
+        assert!(error(load_key(&missing)).contains("无法读取私钥"));
+        assert!(error(load_key(&empty)).contains("中没有私钥"));
+        // 证书文件里没有私钥
+        assert!(error(load_key(&source.cert)).contains("中没有私钥"));
+        assert_eq!(load_certs(&source.cert).unwrap().len(), 1);
+        assert!(load_key(&source.key).is_ok());
+        // 证书和私钥不匹配
+        let (other, _) = generate(&dir, "b", &["localhost"]);
+        assert!(error(certified_key(&source.cert, &other.key)).contains("和私钥无效"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn select_by_sni() {
+        let dir = temp_dir("sni");
+        let (default, default_der) = generate(&dir, "default", &["localhost"]);
+        let (mut exact, exact_der) = generate(&dir, "exact", &["www.example.com"]);
+        let (mut wildcard, wildcard_der) = generate(&dir, "wildcard", &["*.example.com"]);
+        exact.host = Some(String::from("www.example.com"));
+        wildcard.host = Some(String::from("*.example.com"));
+        let store = CertStore::new(vec![wildcard.clone(), exact.clone(), default]).unwrap();
+
+        assert_eq!(
+            selected(&store, Some("www.example.com")),
+            Some(exact_der.clone())
+        );
+        assert_eq!(selected(&store, Some("WWW.Example.COM")), Some(exact_der));
+        assert_eq!(
+            selected(&store, Some("api.example.com")),
+            Some(wildcard_der.clone())
+        );
+        assert_eq!(
+            selected(&store, Some("example.com")),
+            Some(default_der.clone())
+        );
+        assert_eq!(selected(&store, None), Some(default_der));
+
+        // 没有默认证书时不匹配的主机名没有证书
+        let store = CertStore::new(vec![wildcard]).unwrap();
+        assert_eq!(selected(&store, Some("a.example.com")), Some(wildcard_der));
+        assert_eq!(selected(&store, Some("example.org")), None);
+        assert_eq!(selected(&store, None), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reload_keeps_old_certs_on_failure() {
+        let dir = temp_dir("reload");
+        let (source, old_der) = generate(&dir, "a", &["localhost"]);
+        let store = CertStore::new(vec![source.clone()]).unwrap();
+        assert!(!store.changed());
+
+        // 证书写了一半
+        fs::write(&source.cert, "-----BEGIN CERTIFICATE-----\n").unwrap();
+        assert!(store.reload().is_err());
+        assert_eq!(selected(&store, None), Some(old_der));
+
+        // 新的证书和私钥都写好后重新加载成功
+        let (_, new_der) = generate(&dir, "a", &["localhost"]);
+        assert!(store.reload().is_ok());
+        assert_eq!(selected(&store, None), Some(new_der));
+        assert!(!store.changed());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parse_client_cert() {
+        let mut params = CertificateParams::new(vec![
+            String::from("alice.example.com"),
+            String::from("10.0.0.1"),
+        ])
+        .unwrap();
+        params
+            .subject_alt_names
+            .push(SanType::Rfc822Name("alice@example.com".try_into().unwrap()));
+        params.distinguished_name.push(DnType::CommonName, "alice");
+        params
+            .distinguished_name
+            .push(DnType::OrganizationName, "Example");
+        let cert = params.self_signed(&KeyPair::generate().unwrap()).unwrap();
+
+        let client = ClientCert::parse(cert.der()).unwrap();
+        assert!(client.subject.contains("CN=alice"));
+        assert!(client.subject.contains("O=Example"));
+        assert_eq!(client.common_name(), Some("alice"));
+        assert_eq!(
+            client.san,
+            vec![
+                "DNS:alice.example.com",
+                "IP:10.0.0.1",
+                "email:alice@example.com"
+            ]
+        );
+        assert!(ClientCert::parse(b"not a certificate").is_none());
+    }
+}
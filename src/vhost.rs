@@ -0,0 +1,196 @@
+use crate::request::{HttpRequest, HttpVersion};
+use crate::response::{HttpResponse, HttpStatus};
+use crate::router::Router;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+// 主机名匹配规则
+#[derive(Debug)]
+pub enum HostPattern {
+    // 完全匹配，如 example.com
+    Exact(String),
+    // 匹配任意子域名，如 *.example.com
+    Wildcard(String),
+}
+
+impl HostPattern {
+    pub fn parse(pattern: &str) -> Self {
+        let pattern = pattern.trim().to_lowercase();
+        match pattern.strip_prefix("*.") {
+            Some(suffix) => HostPattern::Wildcard(format!(".{}", suffix)),
+            None => HostPattern::Exact(pattern),
+        }
+    }
+
+    // host需要是不含端口的小写主机名
+    pub fn matches(&self, host: &str) -> bool {
+        match self {
+            HostPattern::Exact(name) => name == host,
+            HostPattern::Wildcard(suffix) => {
+                host.ends_with(suffix.as_str()) && host.len() > suffix.len()
+            }
+        }
+    }
+}
+
+// 按Host请求头把请求分发到不同的路由
+#[derive(Default)]
+pub struct VirtualHosts {
+    hosts: Vec<(HostPattern, Arc<Router>)>,
+    default: Option<Arc<Router>>,
+}
+
+impl VirtualHosts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 添加主机，支持 *.example.com 形式的通配子域名
+    pub fn host(mut self, pattern: &str, router: Router) -> Self {
+        self.hosts
+            .push((HostPattern::parse(pattern), Arc::new(router)));
+        self
+    }
+
+    // 没有匹配到主机时使用的路由
+    pub fn default_host(mut self, router: Router) -> Self {
+        self.default = Some(Arc::new(router));
+        self
+    }
+
+    fn find(&self, host: &str) -> Option<&Arc<Router>> {
+        find_host(&self.hosts, host).or(self.default.as_ref())
+    }
+
+    pub fn handle(&self, req: HttpRequest) -> HttpResponse<'static> {
+        let host = match req.host() {
+            Some(host) => host,
+            // HTTP/1.1要求必须有Host请求头
+            None if *req.version() == HttpVersion::V1_1 => {
+                return HttpResponse::new(
+                    HttpStatus::BadRequest,
+                    None::<BTreeMap<&str, &str>>,
+                    Some("缺少Host请求头".as_bytes().to_vec()),
+                );
+            }
+            None => String::new(),
+        };
+        match self.find(&host) {
+            Some(router) => router.handle(req),
+            None => HttpResponse::not_found(None),
+        }
+    }
+}
+
+// 查找主机对应的项，完全匹配优先，其次是后缀最长的通配
+pub fn find_host<'a, T>(entries: &'a [(HostPattern, T)], host: &str) -> Option<&'a T> {
+    let mut wildcard: Option<(usize, &T)> = None;
+    for (pattern, value) in entries {
+        if !pattern.matches(host) {
+            continue;
+        }
+        match pattern {
+            HostPattern::Exact(_) => return Some(value),
+            HostPattern::Wildcard(suffix) => {
+                if wildcard.is_none_or(|(len, _)| suffix.len() > len) {
+                    wildcard = Some((suffix.len(), value));
+                }
+            }
+        }
+    }
+    wildcard.map(|(_, value)| value)
+}
+
+// 单个路由作为默认主机
+impl From<Router> for VirtualHosts {
+    fn from(router: Router) -> Self {
+        VirtualHosts::new().default_host(router)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::Body;
+
+    fn named(name: &'static str) -> Router {
+        Router::new().fallback(move |_: &HttpRequest| {
+            HttpResponse::new(
+                HttpStatus::Ok,
+                None::<BTreeMap<&str, &str>>,
+                Some(name.as_bytes().to_vec()),
+            )
+        })
+    }
+
+    fn send(hosts: &VirtualHosts, head: &str) -> HttpResponse<'static> {
+        hosts.handle(HttpRequest::from(head, Vec::new(), "127.0.0.1").unwrap())
+    }
+
+    fn body(response: &HttpResponse) -> String {
+        match response.body() {
+            Some(Body::Full(bytes)) => String::from_utf8_lossy(bytes).to_string(),
+            _ => String::new(),
+        }
+    }
+
+    #[test]
+    fn parse_pattern() {
+        assert!(matches!(
+            HostPattern::parse(" Example.COM "),
+            HostPattern::Exact(name) if name == "example.com"
+        ));
+        assert!(matches!(
+            HostPattern::parse("*.Example.com"),
+            HostPattern::Wildcard(suffix) if suffix == ".example.com"
+        ));
+        let wildcard = HostPattern::parse("*.example.com");
+        assert!(wildcard.matches("a.example.com"));
+        assert!(wildcard.matches("a.b.example.com"));
+        assert!(!wildcard.matches("example.com"));
+        assert!(!wildcard.matches(".example.com"));
+        assert!(!wildcard.matches("aexample.com"));
+    }
+
+    #[test]
+    fn find_host_prefers_exact_then_longest_wildcard() {
+        let entries = vec![
+            (HostPattern::parse("*.example.com"), "wildcard"),
+            (HostPattern::parse("*.api.example.com"), "api"),
+            (HostPattern::parse("www.example.com"), "www"),
+        ];
+        assert_eq!(find_host(&entries, "www.example.com"), Some(&"www"));
+        assert_eq!(find_host(&entries, "v1.api.example.com"), Some(&"api"));
+        assert_eq!(find_host(&entries, "blog.example.com"), Some(&"wildcard"));
+        assert_eq!(find_host(&entries, "example.org"), None);
+    }
+
+    #[test]
+    fn handle_by_host_header() {
+        let hosts = VirtualHosts::new()
+            .host("*.example.com", named("wildcard"))
+            .host("www.example.com", named("www"))
+            .default_host(named("default"));
+        // 端口去掉、大小写不敏感
+        let response = send(&hosts, "GET / HTTP/1.1\r\nHost: WWW.Example.com:8080\r\n");
+        assert_eq!(body(&response), "www");
+        let response = send(&hosts, "GET / HTTP/1.1\r\nHost: blog.example.com\r\n");
+        assert_eq!(body(&response), "wildcard");
+        let response = send(&hosts, "GET / HTTP/1.1\r\nHost: other.org\r\n");
+        assert_eq!(body(&response), "default");
+        // 没有默认主机时返回404
+        let hosts = VirtualHosts::new().host("www.example.com", named("www"));
+        let response = send(&hosts, "GET / HTTP/1.1\r\nHost: other.org\r\n");
+        assert_eq!(*response.status(), HttpStatus::NotFound);
+    }
+
+    #[test]
+    fn handle_missing_host() {
+        let hosts = VirtualHosts::from(named("default"));
+        let response = send(&hosts, "GET / HTTP/1.1\r\n");
+        assert_eq!(*response.status(), HttpStatus::BadRequest);
+        // HTTP/1.0可以没有Host，使用默认主机
+        let response = send(&hosts, "GET / HTTP/1.0\r\n");
+        assert_eq!(body(&response), "default");
+    }
+}
@@ -0,0 +1,561 @@
+use crate::connection::Connection;
+use crate::error::{Fail, Result};
+use crate::extract::{FromRequest, Rejection};
+use crate::request::{HttpMethod, HttpRequest, HttpVersion};
+use crate::response::{HttpResponse, HttpStatus, Upgrade};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use sha1::{Digest, Sha1};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+// 与Sec-WebSocket-Key拼接后计算Sec-WebSocket-Accept
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+// 默认的最大消息大小
+const MAX_MESSAGE_SIZE: usize = 1 << 20;
+
+// 主动关闭后等待客户端回复关闭帧的时间
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// 操作码
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xa;
+
+// 关闭码
+pub const CLOSE_NORMAL: u16 = 1000;
+pub const CLOSE_GOING_AWAY: u16 = 1001;
+pub const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+pub const CLOSE_INVALID_DATA: u16 = 1007;
+pub const CLOSE_TOO_BIG: u16 = 1009;
+
+// 从请求中提取的WebSocket握手，在处理器中调用on_upgrade返回101响应
+#[derive(Debug)]
+pub struct WebSocketUpgrade {
+    // Sec-WebSocket-Accept的值
+    accept: String,
+    // 客户端请求的子协议
+    protocols: Vec<String>,
+    // 选中的子协议
+    protocol: Option<String>,
+    max_message_size: usize,
+}
+
+// 逗号分隔的请求头中是否包含token，不区分大小写
+fn has_token(req: &HttpRequest, name: &str, token: &str) -> bool {
+    req.headers().get(name).is_some_and(|value| {
+        value
+            .split(',')
+            .any(|v| v.trim().eq_ignore_ascii_case(token))
+    })
+}
+
+impl FromRequest for WebSocketUpgrade {
+    fn from_request(req: &HttpRequest) -> std::result::Result<Self, Rejection> {
+        let reject = |message: &str| Rejection::bad_request("WebSocket握手", message);
+        if *req.method() != HttpMethod::Get || *req.version() != HttpVersion::V1_1 {
+            return Err(reject("需要HTTP/1.1的GET请求"));
+        }
+        if !has_token(req, "upgrade", "websocket") || !has_token(req, "connection", "upgrade") {
+            return Err(reject("缺少Upgrade: websocket"));
+        }
+        if req.headers().get("sec-websocket-version").map(|v| v.trim()) != Some("13") {
+            return Err(reject("只支持版本13"));
+        }
+        // 客户端的key是16字节随机数的base64编码
+        let key = req
+            .headers()
+            .get("sec-websocket-key")
+            .map(|key| key.trim())
+            .unwrap_or("");
+        if STANDARD.decode(key).map_or(true, |key| key.len() != 16) {
+            return Err(reject("无效的Sec-WebSocket-Key"));
+        }
+        let mut hasher = Sha1::new();
+        hasher.update(key.as_bytes());
+        hasher.update(GUID.as_bytes());
+        let protocols = req
+            .headers()
+            .get("sec-websocket-protocol")
+            .map(|p| p.split(',').map(|p| p.trim().to_string()).collect())
+            .unwrap_or_default();
+        Ok(Self {
+            accept: STANDARD.encode(hasher.finalize()),
+            protocols,
+            protocol: None,
+            max_message_size: MAX_MESSAGE_SIZE,
+        })
+    }
+}
+
+impl WebSocketUpgrade {
+    // 客户端请求的子协议
+    pub fn protocols(&self) -> &[String] {
+        &self.protocols
+    }
+
+    // 选择子协议，客户端没有请求时忽略
+    pub fn protocol(mut self, protocol: &str) -> Self {
+        if self.protocols.iter().any(|p| p == protocol) {
+            self.protocol = Some(protocol.to_string());
+        }
+        self
+    }
+
+    pub fn max_message_size(mut self, size: usize) -> Self {
+        self.max_message_size = size;
+        self
+    }
+
+    // 返回101响应，响应发送后在连接的任务中执行callback
+    pub fn on_upgrade<F, Fut>(self, callback: F) -> HttpResponse<'static>
+    where
+        F: FnOnce(WebSocket) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let mut headers = BTreeMap::new();
+        headers.insert(String::from("Upgrade"), String::from("websocket"));
+        headers.insert(String::from("Connection"), String::from("Upgrade"));
+        headers.insert(String::from("Sec-WebSocket-Accept"), self.accept);
+        if let Some(protocol) = self.protocol {
+            headers.insert(String::from("Sec-WebSocket-Protocol"), protocol);
+        }
+        let mut response = HttpResponse::new(HttpStatus::SwitchingProtocols, Some(headers), None);
+        response.remove_header("Content-Type");
+        let max_message_size = self.max_message_size;
+        response.set_upgrade(Upgrade::new(move |stream, buffer| {
+            callback(WebSocket::new(stream, buffer, max_message_size))
+        }));
+        response
+    }
+}
+
+// WebSocket消息
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    // 关闭码和原因
+    Close(Option<(u16, String)>),
+}
+
+// 一个完整的帧
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+// 升级后的WebSocket连接
+pub struct WebSocket {
+    stream: Connection,
+    // 已经读取但还没有解析的数据
+    buffer: Vec<u8>,
+    // 未完成的分片消息的操作码和内容
+    fragments: Option<(u8, Vec<u8>)>,
+    max_message_size: usize,
+    // 已经发送关闭帧
+    closing: bool,
+    // 已经收到关闭帧或连接已断开
+    closed: bool,
+}
+
+impl WebSocket {
+    fn new(stream: Connection, buffer: Vec<u8>, max_message_size: usize) -> Self {
+        Self {
+            stream,
+            buffer,
+            fragments: None,
+            max_message_size,
+            closing: false,
+            closed: false,
+        }
+    }
+
+    // 接收一条消息，分片的消息合并后返回，连接关闭后返回None
+    // 收到ping时自动回复pong，收到关闭帧时自动回复关闭帧
+    pub async fn recv(&mut self) -> Result<Option<Message>> {
+        loop {
+            if self.closed {
+                return Ok(None);
+            }
+            let frame = match self.read_frame().await? {
+                Some(frame) => frame,
+                None => {
+                    self.closed = true;
+                    return Ok(None);
+                }
+            };
+            match frame.opcode {
+                OP_CONTINUATION => {
+                    let (opcode, mut data) = match self.fragments.take() {
+                        Some(fragments) => fragments,
+                        None => return self.fail(CLOSE_PROTOCOL_ERROR, "没有开始的分片消息").await,
+                    };
+                    if data.len() + frame.payload.len() > self.max_message_size {
+                        return self.fail(CLOSE_TOO_BIG, "消息超出大小限制").await;
+                    }
+                    data.extend_from_slice(&frame.payload);
+                    if frame.fin {
+                        return self.message(opcode, data).await.map(Some);
+                    }
+                    self.fragments = Some((opcode, data));
+                }
+                OP_TEXT | OP_BINARY => {
+                    if self.fragments.is_some() {
+                        return self.fail(CLOSE_PROTOCOL_ERROR, "分片消息没有结束").await;
+                    }
+                    if frame.fin {
+                        return self.message(frame.opcode, frame.payload).await.map(Some);
+                    }
+                    self.fragments = Some((frame.opcode, frame.payload));
+                }
+                OP_CLOSE => {
+                    let close = match frame.payload.len() {
+                        0 => None,
+                        1 => return self.fail(CLOSE_PROTOCOL_ERROR, "无效的关闭帧").await,
+                        _ => {
+                            let code = u16::from_be_bytes([frame.payload[0], frame.payload[1]]);
+                            let reason = match String::from_utf8(frame.payload[2..].to_vec()) {
+                                Ok(reason) => reason,
+                                Err(_) => {
+                                    return self.fail(CLOSE_INVALID_DATA, "关闭原因不是UTF-8").await
+                                }
+                            };
+                            if !matches!(code, 1000..=1003 | 1007..=1014 | 3000..=4999) {
+                                return self.fail(CLOSE_PROTOCOL_ERROR, "无效的关闭码").await;
+                            }
+                            Some((code, reason))
+                        }
+                    };
+                    // 回复相同的关闭码后断开
+                    if !self.closing {
+                        self.closing = true;
+                        let payload = close_payload(close.as_ref().map(|(code, _)| (*code, "")));
+                        let _ = self.write_frame(OP_CLOSE, &payload).await;
+                    }
+                    self.closed = true;
+                    let _ = self.stream.shutdown().await;
+                    return Ok(Some(Message::Close(close)));
+                }
+                OP_PING => {
+                    if !self.closing {
+                        self.write_frame(OP_PONG, &frame.payload).await?;
+                    }
+                    return Ok(Some(Message::Ping(frame.payload)));
+                }
+                OP_PONG => return Ok(Some(Message::Pong(frame.payload))),
+                _ => return self.fail(CLOSE_PROTOCOL_ERROR, "未知的操作码").await,
+            }
+        }
+    }
+
+    // 发送一条消息，关闭后不能再发送
+    pub async fn send(&mut self, message: Message) -> Result<()> {
+        if self.closing {
+            return Fail::from("WebSocket已关闭");
+        }
+        match message {
+            Message::Text(text) => self.write_frame(OP_TEXT, text.as_bytes()).await,
+            Message::Binary(data) => self.write_frame(OP_BINARY, &data).await,
+            Message::Ping(data) => self.write_frame(OP_PING, &data).await,
+            Message::Pong(data) => self.write_frame(OP_PONG, &data).await,
+            Message::Close(close) => {
+                self.closing = true;
+                let payload = close_payload(close.as_ref().map(|(c, r)| (*c, r.as_str())));
+                self.write_frame(OP_CLOSE, &payload).await
+            }
+        }
+    }
+
+    // 发送关闭帧，等待客户端回复关闭帧后断开
+    pub async fn close(mut self, code: u16, reason: &str) -> Result<()> {
+        if !self.closing {
+            self.send(Message::Close(Some((code, reason.to_string()))))
+                .await?;
+        }
+        let _ = tokio::time::timeout(CLOSE_TIMEOUT, async {
+            while let Ok(Some(message)) = self.recv().await {
+                if let Message::Close(_) = message {
+                    break;
+                }
+            }
+        })
+        .await;
+        let _ = self.stream.shutdown().await;
+        Ok(())
+    }
+
+    // 完整的消息，文本消息需要是有效的UTF-8
+    async fn message(&mut self, opcode: u8, data: Vec<u8>) -> Result<Message> {
+        if opcode == OP_BINARY {
+            return Ok(Message::Binary(data));
+        }
+        match String::from_utf8(data) {
+            Ok(text) => Ok(Message::Text(text)),
+            Err(_) => self.fail(CLOSE_INVALID_DATA, "文本消息不是UTF-8").await,
+        }
+    }
+
+    // 协议错误时发送关闭帧并断开
+    async fn fail<T>(&mut self, code: u16, reason: &str) -> Result<T> {
+        if !self.closing {
+            self.closing = true;
+            let _ = self
+                .write_frame(OP_CLOSE, &close_payload(Some((code, reason))))
+                .await;
+        }
+        self.closed = true;
+        let _ = self.stream.shutdown().await;
+        Fail::from(format!("WebSocket错误: {}", reason))
+    }
+
+    // 读取一个帧，连接断开时返回None
+    async fn read_frame(&mut self) -> Result<Option<Frame>> {
+        loop {
+            match parse_frame(&mut self.buffer, self.max_message_size) {
+                Ok(Some(frame)) => return Ok(Some(frame)),
+                Ok(None) => {}
+                Err((code, reason)) => return self.fail(code, reason).await,
+            }
+            self.buffer.reserve(8192);
+            if self.stream.read_buf(&mut self.buffer).await? == 0 {
+                return Ok(None);
+            }
+        }
+    }
+
+    // 服务端发送的帧不使用掩码，消息不分片
+    async fn write_frame(&mut self, opcode: u8, payload: &[u8]) -> Result<()> {
+        let mut frame = vec![0x80 | opcode];
+        match payload.len() {
+            len if len < 126 => frame.push(len as u8),
+            len if len <= u16::MAX as usize => {
+                frame.push(126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(payload);
+        self.stream.write_all(&frame).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+}
+
+// 从缓冲区中解析一个帧，数据不完整时返回None
+fn parse_frame(
+    buffer: &mut Vec<u8>,
+    max_message_size: usize,
+) -> std::result::Result<Option<Frame>, (u16, &'static str)> {
+    if buffer.len() < 2 {
+        return Ok(None);
+    }
+    let fin = buffer[0] & 0x80 != 0;
+    let opcode = buffer[0] & 0x0f;
+    // 没有协商扩展，保留位必须为0
+    if buffer[0] & 0x70 != 0 {
+        return Err((CLOSE_PROTOCOL_ERROR, "保留位不为0"));
+    }
+    // 客户端发送的帧必须使用掩码
+    if buffer[1] & 0x80 == 0 {
+        return Err((CLOSE_PROTOCOL_ERROR, "客户端的帧没有使用掩码"));
+    }
+    let (len, start) = match buffer[1] & 0x7f {
+        126 if buffer.len() < 4 => return Ok(None),
+        126 => (u16::from_be_bytes([buffer[2], buffer[3]]) as u64, 4),
+        127 if buffer.len() < 10 => return Ok(None),
+        127 => {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&buffer[2..10]);
+            (u64::from_be_bytes(bytes), 10)
+        }
+        len => (len as u64, 2),
+    };
+    // 控制帧不能分片，长度不超过125
+    if opcode >= OP_CLOSE && (!fin || len > 125) {
+        return Err((CLOSE_PROTOCOL_ERROR, "无效的控制帧"));
+    }
+    // 根据声明的长度提前拒绝，不等数据全部到达
+    if len > max_message_size as u64 {
+        return Err((CLOSE_TOO_BIG, "消息超出大小限制"));
+    }
+    let end = start + 4 + len as usize;
+    if buffer.len() < end {
+        return Ok(None);
+    }
+    let mask = [
+        buffer[start],
+        buffer[start + 1],
+        buffer[start + 2],
+        buffer[start + 3],
+    ];
+    let payload = buffer[start + 4..end]
+        .iter()
+        .enumerate()
+        .map(|(i, b)| b ^ mask[i % 4])
+        .collect();
+    buffer.drain(..end);
+    Ok(Some(Frame {
+        fin,
+        opcode,
+        payload,
+    }))
+}
+
+// 关闭帧的内容，原因截断到控制帧的长度限制内
+fn close_payload(close: Option<(u16, &str)>) -> Vec<u8> {
+    let (code, reason) = match close {
+        Some(close) => close,
+        None => return Vec::new(),
+    };
+    let mut payload = code.to_be_bytes().to_vec();
+    let mut end = reason.len().min(123);
+    while !reason.is_char_boundary(end) {
+        end -= 1;
+    }
+    payload.extend_from_slice(&reason.as_bytes()[..end]);
+    payload
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MASK: [u8; 4] = [0x12, 0x34, 0x56, 0x78];
+
+    // 客户端发送的帧，first为FIN和操作码所在的字节
+    fn client_frame(first: u8, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![first];
+        match payload.len() {
+            len if len < 126 => frame.push(0x80 | len as u8),
+            len if len <= u16::MAX as usize => {
+                frame.push(0x80 | 126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(0x80 | 127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(&MASK);
+        frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ MASK[i % 4]));
+        frame
+    }
+
+    fn error_code(buffer: &[u8]) -> Option<u16> {
+        parse_frame(&mut buffer.to_vec(), MAX_MESSAGE_SIZE)
+            .err()
+            .map(|(code, _)| code)
+    }
+
+    #[test]
+    fn parse_masked_frame() {
+        let mut buffer = client_frame(0x80 | OP_TEXT, b"hello");
+        let frame = parse_frame(&mut buffer, MAX_MESSAGE_SIZE).unwrap().unwrap();
+        assert!(frame.fin);
+        assert_eq!(frame.opcode, OP_TEXT);
+        assert_eq!(frame.payload, b"hello");
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn parse_extended_length() {
+        let payload = vec![7u8; 300];
+        let mut buffer = client_frame(0x80 | OP_BINARY, &payload);
+        let frame = parse_frame(&mut buffer, MAX_MESSAGE_SIZE).unwrap().unwrap();
+        assert_eq!(frame.payload, payload);
+        let payload = vec![9u8; 70000];
+        let mut buffer = client_frame(0x80 | OP_BINARY, &payload);
+        let frame = parse_frame(&mut buffer, MAX_MESSAGE_SIZE).unwrap().unwrap();
+        assert_eq!(frame.payload, payload);
+    }
+
+    #[test]
+    fn parse_incomplete_frame() {
+        let frame = client_frame(0x80 | OP_TEXT, b"hello");
+        for len in 0..frame.len() {
+            let mut buffer = frame[..len].to_vec();
+            assert!(parse_frame(&mut buffer, MAX_MESSAGE_SIZE)
+                .unwrap()
+                .is_none());
+            // 数据不完整时不消耗缓冲区
+            assert_eq!(buffer.len(), len);
+        }
+    }
+
+    #[test]
+    fn reject_unmasked_frame() {
+        let mut frame = client_frame(0x80 | OP_TEXT, b"hi");
+        frame[1] &= 0x7f;
+        assert_eq!(error_code(&frame), Some(CLOSE_PROTOCOL_ERROR));
+    }
+
+    #[test]
+    fn reject_reserved_bits() {
+        let frame = client_frame(0x80 | 0x40 | OP_TEXT, b"hi");
+        assert_eq!(error_code(&frame), Some(CLOSE_PROTOCOL_ERROR));
+    }
+
+    #[test]
+    fn parse_fragmented_frames() {
+        let mut buffer = client_frame(OP_TEXT, b"hel");
+        buffer.extend(client_frame(0x80 | OP_PING, b"p"));
+        buffer.extend(client_frame(0x80 | OP_CONTINUATION, b"lo"));
+        let first = parse_frame(&mut buffer, MAX_MESSAGE_SIZE).unwrap().unwrap();
+        assert!(!first.fin);
+        assert_eq!((first.opcode, &first.payload[..]), (OP_TEXT, &b"hel"[..]));
+        // 分片之间可以插入控制帧
+        let ping = parse_frame(&mut buffer, MAX_MESSAGE_SIZE).unwrap().unwrap();
+        assert_eq!((ping.opcode, &ping.payload[..]), (OP_PING, &b"p"[..]));
+        let last = parse_frame(&mut buffer, MAX_MESSAGE_SIZE).unwrap().unwrap();
+        assert!(last.fin);
+        assert_eq!(
+            (last.opcode, &last.payload[..]),
+            (OP_CONTINUATION, &b"lo"[..])
+        );
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn reject_oversized_frame() {
+        // 只有帧头也按声明的长度拒绝
+        let mut header = vec![0x80 | OP_BINARY, 0x80 | 127];
+        header.extend_from_slice(&(u64::MAX).to_be_bytes());
+        assert_eq!(error_code(&header), Some(CLOSE_TOO_BIG));
+        let frame = client_frame(0x80 | OP_BINARY, &[0u8; 101]);
+        assert_eq!(
+            parse_frame(&mut frame.clone(), 100)
+                .err()
+                .map(|(code, _)| code),
+            Some(CLOSE_TOO_BIG)
+        );
+    }
+
+    #[test]
+    fn reject_invalid_control_frames() {
+        // 控制帧的长度不能超过125
+        let frame = client_frame(0x80 | OP_PING, &[0u8; 126]);
+        assert_eq!(error_code(&frame), Some(CLOSE_PROTOCOL_ERROR));
+        let frame = client_frame(0x80 | OP_CLOSE, &[0u8; 126]);
+        assert_eq!(error_code(&frame), Some(CLOSE_PROTOCOL_ERROR));
+        // 控制帧不能分片
+        let frame = client_frame(OP_PONG, b"x");
+        assert_eq!(error_code(&frame), Some(CLOSE_PROTOCOL_ERROR));
+        let frame = client_frame(0x80 | OP_PING, &[0u8; 125]);
+        assert!(parse_frame(&mut frame.clone(), MAX_MESSAGE_SIZE)
+            .unwrap()
+            .is_some());
+    }
+}